@@ -0,0 +1,99 @@
+//! Plays turn-based Bingo on 10x10 boards with 10 players and times the moves, the game
+//! end checks and the rankings. Run it with `cargo bench --bench bingo`.
+
+use std::time::{Duration, Instant};
+
+use bingo_backend::games::{
+    bingo::{Bingo, BingoPlayerMessages, BingoStart, Board, GameState, NumberPool, WinRules},
+    GamePlayer, GameTrait, Player, PlayerGameData,
+};
+use rand::seq::SliceRandom;
+
+const BOARD_SIZE: u16 = 10;
+const PLAYERS: usize = 10;
+const GAMES: u32 = 20;
+
+fn start() -> BingoStart {
+    BingoStart {
+        board_size: BOARD_SIZE,
+        board_time_limit: None,
+        win_rules: WinRules::classic(BOARD_SIZE),
+        caller_mode: None,
+        claim_penalty: None,
+        cards: 1,
+        pool: NumberPool::new(None, None, BOARD_SIZE).unwrap(),
+    }
+}
+
+/// A game with every board readied, before the first number is called.
+fn new_game() -> (Bingo, Vec<GamePlayer>) {
+    let players = (0..PLAYERS)
+        .map(|i| Player {
+            id: format!("p{}", i),
+            name: format!("Player {}", i),
+            team: None,
+        })
+        .collect::<Vec<_>>();
+    let mut game_players = players
+        .iter()
+        .map(|player| GamePlayer {
+            player: player.clone(),
+            data: PlayerGameData::BingoPlayerData(Bingo::create_player_data(
+                &start(),
+                &players,
+                &player.id,
+            )),
+            connected: true,
+        })
+        .collect::<Vec<_>>();
+    let mut bingo = Bingo::start_game(start(), &game_players, "p0");
+    for player in players {
+        let board = Board::random(BOARD_SIZE, u32::from(BOARD_SIZE).pow(2)).unwrap();
+        bingo
+            .handle_player_message(
+                &player.id,
+                &mut game_players,
+                BingoPlayerMessages::ReadyBoard(vec![board]),
+            )
+            .unwrap();
+    }
+    (bingo, game_players)
+}
+
+fn main() {
+    let mut rng = rand::thread_rng();
+    let (mut moves, mut rankings) = (0, 0);
+    let (mut move_time, mut ranking_time) = (Duration::ZERO, Duration::ZERO);
+    for _ in 0..GAMES {
+        let (mut bingo, mut players) = new_game();
+        let mut numbers = (1..=u32::from(BOARD_SIZE).pow(2)).collect::<Vec<_>>();
+        numbers.shuffle(&mut rng);
+        for number in numbers {
+            let turn = match &bingo.game_state {
+                GameState::GameRunning(data) => data.turn.clone(),
+                _ => unreachable!("every board is ready"),
+            };
+            let started = Instant::now();
+            bingo
+                .handle_player_message(&turn, &mut players, BingoPlayerMessages::Move(number))
+                .unwrap();
+            let ended = bingo.is_game_end(&players);
+            move_time += started.elapsed();
+            moves += 1;
+
+            let started = Instant::now();
+            std::hint::black_box(bingo.get_rankings(&players));
+            ranking_time += started.elapsed();
+            rankings += 1;
+            if ended {
+                break;
+            }
+        }
+    }
+    println!(
+        "{}x{} boards, {} players, {} games",
+        BOARD_SIZE, BOARD_SIZE, PLAYERS, GAMES
+    );
+    println!("move and end check: {:?} per move", move_time / moves);
+    println!("rankings:           {:?} per call", ranking_time / rankings);
+}
@@ -0,0 +1,155 @@
+//! Plays ten moves in a 10x10 Boxes room with 4 players and in a 5x5 Bingo room with 8
+//! players, and prints the average size of the room event sent after each move as JSON,
+//! MessagePack and CBOR. Run it with `cargo bench --bench payload`.
+
+use bingo_backend::{
+    data::{GameMessage, Room, RoomState, ServerResponse},
+    games::{
+        bingo::{BingoPlayerMessages, BingoStart, Board, GameState, NumberPool, WinRules},
+        boxes::{BoxesPlayerMessages, BoxesStart, Move},
+        Game, GamePlayer, GameTrait, Player, PlayerMessages, StartMessages,
+    },
+    logic::{GameData, GameEvents, PlayerEvents, RoomUpdate},
+    utils::now_millis,
+    ws::{Encoding, ServerFrame, ServerPayload},
+};
+
+const MOVES: u32 = 10;
+const ENCODINGS: [(&str, Encoding); 3] = [
+    ("JSON", Encoding::Json),
+    ("MessagePack", Encoding::Msgpack),
+    ("CBOR", Encoding::Cbor),
+];
+
+/// A room running the game, with every player connected.
+fn room(players: usize, start: StartMessages) -> Room {
+    let players = (0..players)
+        .map(|i| Player {
+            id: format!("p{}", i),
+            name: format!("Player {}", i),
+            team: None,
+        })
+        .collect::<Vec<_>>();
+    let game_players = players
+        .iter()
+        .map(|player| GamePlayer {
+            player: player.clone(),
+            data: Game::create_player_data(&start, &players, &player.id),
+            connected: true,
+        })
+        .collect::<Vec<_>>();
+    let mut room = Room::new("PAYLOAD".into(), players[0].clone(), 16);
+    room.state = RoomState::Game(GameData {
+        game: Game::start_game(start, &game_players, &players[0].id),
+        players: game_players,
+        host: players[0].id.clone(),
+        paused: false,
+        pause_votes: vec![],
+        undo_votes: vec![],
+        started_at: now_millis(),
+        ended_at: None,
+        waiting_since: now_millis(),
+        paused_at: None,
+        moves: vec![],
+        deadline_watched: false,
+    });
+    room
+}
+
+fn game(room: &Room) -> &Game {
+    &room.state.as_game().expect("the game is running").game
+}
+
+/// Average size in bytes of the frame sent after each move, in every encoding.
+async fn event_sizes(
+    mut room: Room,
+    next_move: impl Fn(&Room, u32) -> (String, PlayerMessages),
+) -> Vec<usize> {
+    let mut sizes = vec![0; ENCODINGS.len()];
+    for (seq, k) in (0..MOVES).enumerate() {
+        let (player_id, message) = next_move(&room, k);
+        room.handle_player_message(&player_id, PlayerEvents::GameMessage(message))
+            .await
+            .unwrap();
+        let frame = ServerFrame {
+            version: 1,
+            payload: ServerPayload::Event {
+                seq: seq as u64 + 1,
+                event: Box::new(ServerResponse::GameMessage(GameMessage {
+                    event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+                    room: room.clone(),
+                    seq: seq as u64 + 1,
+                })),
+            },
+        };
+        for (size, (_, encoding)) in sizes.iter_mut().zip(ENCODINGS) {
+            *size += encoding.encode(&frame).unwrap().as_bytes().len();
+        }
+    }
+    sizes
+        .into_iter()
+        .map(|size| size / MOVES as usize)
+        .collect()
+}
+
+async fn boxes() -> Vec<usize> {
+    let start = StartMessages::BoxesStart(BoxesStart {
+        board_width: 10,
+        board_height: 10,
+    });
+    event_sizes(room(4, start), |room, k| {
+        let turn = game(room).as_boxes().unwrap().turn.clone();
+        let message = BoxesPlayerMessages::Move(Move { edge_id: k * 7 + 1 });
+        (turn, PlayerMessages::BoxesPlayerMessages(message))
+    })
+    .await
+}
+
+async fn bingo() -> Vec<usize> {
+    const BOARD_SIZE: u16 = 5;
+    let start = || BingoStart {
+        board_size: BOARD_SIZE,
+        board_time_limit: None,
+        win_rules: WinRules::classic(BOARD_SIZE),
+        caller_mode: None,
+        claim_penalty: None,
+        cards: 1,
+        pool: NumberPool::new(None, None, BOARD_SIZE).unwrap(),
+    };
+    let mut room = room(8, StartMessages::BingoStart(start()));
+    for i in 0..8 {
+        let board = Board::random(BOARD_SIZE, u32::from(BOARD_SIZE).pow(2)).unwrap();
+        let message = BingoPlayerMessages::ReadyBoard(vec![board]);
+        room.handle_player_message(
+            &format!("p{}", i),
+            PlayerEvents::GameMessage(PlayerMessages::BingoMessages(message)),
+        )
+        .await
+        .unwrap();
+    }
+    event_sizes(room, |room, k| {
+        let turn = match &game(room).as_bingo().unwrap().game_state {
+            GameState::GameRunning(data) => data.turn.clone(),
+            _ => unreachable!("every board is ready"),
+        };
+        let message = BingoPlayerMessages::Move(k + 1);
+        (turn, PlayerMessages::BingoMessages(message))
+    })
+    .await
+}
+
+#[tokio::main]
+async fn main() {
+    println!("| Game | {} |", ENCODINGS.map(|(name, _)| name).join(" | "));
+    println!("| --- | --- | --- | --- |");
+    for (name, sizes) in [
+        ("Boxes, 10x10, 4 players", boxes().await),
+        ("Bingo, 5x5, 8 players", bingo().await),
+    ] {
+        let sizes = sizes
+            .iter()
+            .map(|size| format!("{} B", size))
+            .collect::<Vec<_>>();
+        println!("| {} | {} |", name, sizes.join(" | "));
+    }
+}
@@ -0,0 +1,191 @@
+use async_graphql::{Context, Json, Object};
+
+use crate::{
+    data::{Announcement, GameMessage, PlayerRemoved, Room, RoomClosed, ServerResponse, Storage},
+    logic::{GameEvents, RoomUpdate},
+};
+
+/// Token operators must present to use the admin API. The API is disabled when unset.
+#[derive(Default, Clone)]
+pub struct AdminConfig {
+    pub token: Option<String>,
+}
+
+impl AdminConfig {
+    pub fn from_env() -> Self {
+        Self {
+            token: std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+        }
+    }
+
+    pub fn authorize(&self, token: &str) -> Result<(), async_graphql::Error> {
+        match &self.token {
+            Some(admin_token) if constant_time_eq(admin_token.as_bytes(), token.as_bytes()) => {
+                Ok(())
+            }
+            Some(_) => Err("Invalid admin token".into()),
+            None => Err("Admin API disabled".into()),
+        }
+    }
+}
+
+/// Compares both strings in full, so the time taken does not tell how much of a guess matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let len = a.len().max(b.len());
+    let diff = (0..len).fold(a.len() ^ b.len(), |diff, i| {
+        let x = a.get(i).copied().unwrap_or(0);
+        let y = b.get(i).copied().unwrap_or(0);
+        diff | usize::from(x ^ y)
+    });
+    diff == 0
+}
+
+pub struct AdminQuery;
+
+#[Object]
+impl AdminQuery {
+    pub async fn rooms(&self, ctx: &Context<'_>) -> Result<Vec<Room>, async_graphql::Error> {
+        let data = ctx.data::<Storage>()?;
+        let rooms = data.read_rooms().await;
+        Ok(rooms.values().cloned().collect())
+    }
+
+    #[tracing::instrument(skip(self, ctx))]
+    pub async fn room(
+        &self,
+        ctx: &Context<'_>,
+        room_id: String,
+    ) -> Result<Room, async_graphql::Error> {
+        let data = ctx.data::<Storage>()?;
+        let rooms = data.read_rooms().await;
+        Ok(rooms.get(&room_id).ok_or("Room does not exist")?.clone())
+    }
+
+    /// Full serialized room, including game state hidden from the regular schema.
+    #[tracing::instrument(skip(self, ctx))]
+    pub async fn room_dump(
+        &self,
+        ctx: &Context<'_>,
+        room_id: String,
+    ) -> Result<Json<Room>, async_graphql::Error> {
+        let data = ctx.data::<Storage>()?;
+        let rooms = data.read_rooms().await;
+        Ok(Json(
+            rooms.get(&room_id).ok_or("Room does not exist")?.clone(),
+        ))
+    }
+}
+
+pub struct AdminMutation;
+
+#[Object]
+impl AdminMutation {
+    #[tracing::instrument(skip(self, ctx))]
+    pub async fn end_game(
+        &self,
+        ctx: &Context<'_>,
+        room_id: String,
+    ) -> Result<bool, async_graphql::Error> {
+        let data = ctx.data::<Storage>()?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+            let room = rooms
+                .get_mut(&room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exist"))?;
+            if room.state.as_game().is_none() {
+                return Err("Game Not Started".into());
+            }
+            room.state.end_game();
+            tracing::info!("admin ended game");
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+
+    #[tracing::instrument(skip(self, ctx, reason))]
+    pub async fn close_room(
+        &self,
+        ctx: &Context<'_>,
+        room_id: String,
+        reason: String,
+    ) -> Result<bool, async_graphql::Error> {
+        let data = ctx.data::<Storage>()?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+            rooms
+                .remove(&room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exist"))?
+        };
+        tracing::info!("admin closed room");
+
+        room.broadcast(ServerResponse::RoomClosed(RoomClosed {
+            room_id,
+            reason,
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+
+    #[tracing::instrument(skip(self, ctx))]
+    pub async fn kick_player(
+        &self,
+        ctx: &Context<'_>,
+        room_id: String,
+        player_id: String,
+    ) -> Result<bool, async_graphql::Error> {
+        let data = ctx.data::<Storage>()?;
+
+        let (room, player) = {
+            let mut rooms = data.write_rooms().await;
+
+            let room = rooms
+                .get_mut(&room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exist"))?;
+
+            let player = room.remove_player(&player_id)?;
+            room.state.handle_game_end();
+
+            (room.clone(), player)
+        };
+        tracing::info!("admin kicked player");
+
+        room.broadcast(ServerResponse::PlayerRemoved(PlayerRemoved {
+            player,
+
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+
+    /// Sends a message to every subscriber of every room.
+    pub async fn announce(
+        &self,
+        ctx: &Context<'_>,
+        message: String,
+    ) -> Result<u32, async_graphql::Error> {
+        let data = ctx.data::<Storage>()?;
+        let rooms = {
+            let rooms = data.read_rooms().await;
+            rooms.values().cloned().collect::<Vec<_>>()
+        };
+        let room_count = rooms.len() as u32;
+        let futures = rooms.iter().map(|room| {
+            room.broadcast(ServerResponse::Announcement(Announcement {
+                message: message.clone(),
+                seq: 0,
+            }))
+        });
+        futures::future::join_all(futures).await;
+        Ok(room_count)
+    }
+}
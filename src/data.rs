@@ -1,25 +1,192 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex, MutexGuard},
+};
 
 use async_graphql::*;
-use async_trait::async_trait;
 use serde::Serialize;
-use tokio::sync::{mpsc::Sender, RwLock};
+use tokio::sync::{
+    mpsc::{error::TrySendError, Sender},
+    RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+use tracing::Instrument;
 
 use crate::{
-    games::GameTrait,
-    logic::{GameData, GameEvents, GamePlayer},
+    games::{
+        bingo::{
+            Bingo, BingoStart, BoardSizeLimits, CallerMode, ClaimPenalty, NumberPool, WinPattern,
+            WinRules,
+        },
+        tambola::TambolaStart,
+        GameKind, GamePlayer, GameTrait, Player, Rank, StartMessages, TeamRank,
+    },
+    logic::{GameData, GameEvents, PlayerStats},
+    utils::now_millis,
 };
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Storage {
     pub private_rooms: Arc<RwLock<HashMap<String, Room>>>,
+    /// Milliseconds Bingo players get to ready their boards, unlimited if `None`.
+    pub board_time_limit: Option<u64>,
+    pub bingo_board_sizes: BoardSizeLimits,
+}
+
+impl Storage {
+    pub async fn read_rooms(&self) -> RwLockReadGuard<'_, HashMap<String, Room>> {
+        self.private_rooms
+            .read()
+            .instrument(tracing::debug_span!("rooms_lock_wait", mode = "read"))
+            .await
+    }
+
+    pub async fn write_rooms(&self) -> RwLockWriteGuard<'_, HashMap<String, Room>> {
+        self.private_rooms
+            .write()
+            .instrument(tracing::debug_span!("rooms_lock_wait", mode = "write"))
+            .await
+    }
+
+    /// Start message for a Bingo game with this server's board limits.
+    pub fn bingo_start(
+        &self,
+        board_size: u16,
+        win_rules: WinRules,
+        caller_mode: Option<CallerMode>,
+        claim_penalty: Option<ClaimPenalty>,
+        cards: Option<u16>,
+        pool: NumberPool,
+    ) -> Result<BingoStart, anyhow::Error> {
+        self.bingo_board_sizes.check(board_size)?;
+        ClaimPenalty::check(claim_penalty, caller_mode.as_ref())?;
+        Ok(BingoStart {
+            board_size,
+            board_time_limit: self.board_time_limit,
+            win_rules,
+            caller_mode,
+            claim_penalty,
+            cards: Bingo::check_cards(cards)?,
+            pool,
+        })
+    }
+}
+
+/// Recent events each room keeps so reconnecting clients can catch up.
+pub const EVENT_BUFFER_SIZE: usize = 128;
+
+/// Events a subscriber may fall behind by before it has to resync.
+pub const SUBSCRIBER_BUFFER_SIZE: usize = 32;
+
+/// Numbers the events of a room, keeps the most recent ones and delivers them to the
+/// subscribed players in order.
+///
+/// Events are handed to the subscribers without waiting, so the log is only ever locked
+/// briefly. A subscriber whose channel is full misses events until it has room again, and
+/// then gets a single [`ResyncRequired`] in their place.
+#[derive(Default)]
+pub struct EventLog {
+    seq: u64,
+    recent: VecDeque<RoomEvent>,
+    subscribers: Vec<Subscriber>,
+}
+
+struct Subscriber {
+    player_id: String,
+    channel: Sender<RoomEvent>,
+    /// Set once an event could not be delivered.
+    lagging: bool,
+}
+
+impl EventLog {
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    fn push(&mut self, event: ServerResponse) -> RoomEvent {
+        self.seq += 1;
+        let event = RoomEvent::new(self.seq, event);
+        if self.recent.len() == EVENT_BUFFER_SIZE {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(event.clone());
+        event
+    }
+
+    /// Events after `last_seen`, or `None` if some of them are no longer kept.
+    pub fn since(&self, last_seen: u64) -> Option<Vec<RoomEvent>> {
+        if last_seen > self.seq {
+            return None;
+        }
+        match self.recent.front() {
+            Some(first) if first.seq > last_seen + 1 => None,
+            _ => Some(
+                self.recent
+                    .iter()
+                    .filter(|e| e.seq > last_seen)
+                    .cloned()
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn subscribe(&mut self, player_id: &str, channel: Sender<RoomEvent>) {
+        self.unsubscribe(player_id);
+        self.subscribers.push(Subscriber {
+            player_id: player_id.into(),
+            channel,
+            lagging: false,
+        });
+    }
+
+    pub fn unsubscribe(&mut self, player_id: &str) {
+        self.subscribers.retain(|s| s.player_id != player_id);
+    }
+
+    /// Numbers the event and hands it to every subscriber. Lagging subscribers get
+    /// `resync` instead, once their channel has room for it.
+    fn deliver(&mut self, event: ServerResponse, resync: impl Fn() -> ServerResponse) {
+        self.subscribers.retain(|s| !s.channel.is_closed());
+        let event = self.push(event);
+        for subscriber in self.subscribers.iter_mut() {
+            let sent = if subscriber.lagging {
+                subscriber
+                    .channel
+                    .try_send(RoomEvent::new(event.seq, resync()))
+            } else {
+                subscriber.channel.try_send(event.clone())
+            };
+            match sent {
+                Ok(()) => subscriber.lagging = false,
+                Err(TrySendError::Full(_)) => {
+                    if !subscriber.lagging {
+                        tracing::debug!(
+                            player_id = %subscriber.player_id,
+                            seq = event.seq,
+                            "subscriber lagging behind"
+                        );
+                    }
+                    subscriber.lagging = true;
+                }
+                Err(TrySendError::Closed(_)) => {}
+            }
+        }
+    }
 }
 
+/// Most players any room can hold, and the capacity of rooms created without one.
+pub const MAX_ROOM_CAPACITY: u32 = 16;
+
 #[derive(Serialize, SimpleObject, Clone)]
 #[graphql(complex)]
 pub struct Room {
     id: String,
     pub state: RoomState,
+    /// Most players that can join the room.
+    pub capacity: u32,
+
+    #[serde(skip_serializing)]
+    #[graphql(skip)]
+    pub events: Arc<Mutex<EventLog>>,
 }
 
 #[ComplexObject]
@@ -43,18 +210,68 @@ impl Room {
 }
 
 impl Room {
-    pub fn new(id: String, player: Player) -> Self {
+    pub fn new(id: String, player: Player, capacity: u32) -> Self {
         Self {
             id,
+            capacity,
+            events: Default::default(),
             state: RoomState::Lobby(LobbyData {
+                host: player.id.clone(),
                 players: vec![LobbyPlayer {
                     player,
-                    send_channel: None,
+                    ready: false,
+                    connected: false,
                 }],
                 last_game: None,
+                proposal: None,
             }),
         }
     }
+
+    /// Adds the player to the lobby unless the room is already full.
+    pub fn add_player(&mut self, player: Player) -> Result<(), anyhow::Error> {
+        let count = match &self.state {
+            RoomState::Lobby(data) => data.players.len(),
+            RoomState::Game(data) => data.players.len(),
+        };
+        if count >= self.capacity as usize && self.state.get_player(&player.id).is_none() {
+            return Err(anyhow::anyhow!("Room is full"));
+        }
+        self.state.add_player(player)
+    }
+
+    /// Locks the room's event log. It is never held across an `.await`.
+    pub fn events(&self) -> MutexGuard<'_, EventLog> {
+        self.events
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Removes the player from the room and stops sending them events.
+    pub fn remove_player(&mut self, player_id: &str) -> Result<Player, anyhow::Error> {
+        let player = self.state.remove_player(player_id)?;
+        self.events().unsubscribe(player_id);
+        Ok(player)
+    }
+
+    /// Numbers the message and sends it to every subscribed player, without waiting for
+    /// any of them.
+    pub async fn broadcast(&self, message: ServerResponse) {
+        let mut events = self.events();
+        let _span = tracing::debug_span!(
+            "broadcast",
+            event = message.kind(),
+            seq = events.seq() + 1,
+            recipients = events.subscribers.len()
+        )
+        .entered();
+        events.deliver(message, || {
+            ServerResponse::ResyncRequired(ResyncRequired {
+                room: self.clone(),
+                seq: 0,
+            })
+        });
+    }
 }
 
 #[derive(Union, Serialize, Clone)]
@@ -72,7 +289,8 @@ impl RoomState {
                 } else {
                     lobbydata.players.push(LobbyPlayer {
                         player,
-                        send_channel: None,
+                        ready: false,
+                        connected: false,
                     });
 
                     Ok(())
@@ -90,21 +308,17 @@ impl RoomState {
 
     pub fn is_empty(&self) -> bool {
         match self {
-            RoomState::Lobby(data) => !data.players.iter().any(|p| p.send_channel.is_some()),
-            RoomState::Game(data) => !data.players.iter().any(|p| p.send_channel.is_some()),
+            RoomState::Lobby(data) => !data.players.iter().any(|p| p.connected),
+            RoomState::Game(data) => !data.players.iter().any(|p| p.connected),
         }
     }
 
-    pub fn set_player_channel(
-        &mut self,
-        player_id: String,
-        channel: Sender<ServerResponse>,
-    ) -> Result<(), anyhow::Error> {
+    pub fn connect_player(&mut self, player_id: &str) -> Result<(), anyhow::Error> {
         match self {
             RoomState::Lobby(data) => {
                 let pl = data.players.iter_mut().find(|p| p.player.id == player_id);
                 if let Some(pl) = pl {
-                    pl.send_channel = Some(channel);
+                    pl.connected = true;
                     Ok(())
                 } else {
                     Err(anyhow::anyhow!("Player does not exist"))
@@ -113,7 +327,7 @@ impl RoomState {
             RoomState::Game(data) => {
                 let pl = data.players.iter_mut().find(|p| p.player.id == player_id);
                 if let Some(pl) = pl {
-                    pl.send_channel = Some(channel);
+                    pl.connected = true;
                     Ok(())
                 } else {
                     Err(anyhow::anyhow!("Player does not exist"))
@@ -137,18 +351,12 @@ impl RoomState {
         }
     }
 
-    pub async fn broadcast(self, message: ServerResponse) {
-        match self {
-            RoomState::Lobby(data) => data.broadcast(message).await,
-            RoomState::Game(data) => data.broadcast(message).await,
-        }
-    }
     pub fn disconnect_player(&mut self, player_id: &str) -> Result<(), anyhow::Error> {
-        log::info!("Removing player {}", player_id);
+        tracing::info!(player_id, "disconnecting player");
         match self {
             RoomState::Lobby(data) => {
                 if let Some(player) = data.players.iter_mut().find(|p| p.player.id == player_id) {
-                    player.send_channel = None;
+                    player.connected = false;
                     Ok(())
                 } else {
                     Err(anyhow::anyhow!("Player does not exist"))
@@ -156,7 +364,7 @@ impl RoomState {
             }
             RoomState::Game(data) => {
                 if let Some(player) = data.players.iter_mut().find(|p| p.player.id == player_id) {
-                    player.send_channel = None;
+                    player.connected = false;
                     Ok(())
                 } else {
                     Err(anyhow::anyhow!("Player does not exist"))
@@ -165,6 +373,13 @@ impl RoomState {
         }
     }
 
+    /// Tags the current tracing span with the type of the running game, if any.
+    pub fn record_game_kind(&self) {
+        if let Self::Game(data) = self {
+            tracing::Span::current().record("game", data.game.kind());
+        }
+    }
+
     pub fn as_game(&self) -> Option<&GameData> {
         if let Self::Game(v) = self {
             Some(v)
@@ -176,36 +391,72 @@ impl RoomState {
     pub fn handle_game_end(&mut self) {
         if let Self::Game(data) = self {
             if data.is_game_end() {
-                let lobby_player = data
-                    .players
-                    .iter()
-                    .cloned()
-                    .map(|p| LobbyPlayer {
-                        player: p.player,
-                        send_channel: p.send_channel,
-                    })
-                    .collect();
-                data.players.iter_mut().for_each(|p| p.send_channel = None);
-
-                *self = Self::Lobby(LobbyData {
-                    players: lobby_player,
-                    last_game: {
-                        if data.game.is_game_running() {
-                            Some(LastGame {
-                                last_game: data.clone(),
-                                leader_board: data.get_rankings(),
-                            })
-                        } else {
-                            None
-                        }
-                    },
+                self.end_game();
+            }
+        }
+    }
+
+    /// Moves a running game back to the lobby, keeping its results as the last game.
+    pub fn end_game(&mut self) {
+        if let Self::Game(data) = self {
+            let lobby_player = data
+                .players
+                .iter()
+                .cloned()
+                .map(|p| LobbyPlayer {
+                    player: p.player,
+                    ready: false,
+                    connected: p.connected,
                 })
+                .collect();
+            data.players.iter_mut().for_each(|p| p.connected = false);
+            data.ended_at = Some(now_millis());
+
+            *self = Self::Lobby(LobbyData {
+                host: data.host.clone(),
+                players: lobby_player,
+                proposal: None,
+                last_game: {
+                    if data.game.is_game_running() {
+                        Some(LastGame {
+                            last_game: data.clone(),
+                            leader_board: data.get_rankings(),
+                            team_leader_board: data.get_team_rankings(),
+                            player_stats: data.get_player_stats(),
+                            duration: data
+                                .ended_at
+                                .unwrap_or_default()
+                                .saturating_sub(data.started_at),
+                        })
+                    } else {
+                        None
+                    }
+                },
+            })
+        }
+    }
+
+    pub fn set_player_team(
+        &mut self,
+        player_id: &str,
+        team: Option<u32>,
+    ) -> Result<Player, anyhow::Error> {
+        match self {
+            RoomState::Lobby(data) => {
+                let pl = data
+                    .players
+                    .iter_mut()
+                    .find(|p| p.player.id == player_id)
+                    .ok_or_else(|| anyhow::anyhow!("Player does not exist"))?;
+                pl.player.team = team;
+                Ok(pl.player.clone())
             }
+            RoomState::Game(_) => Err(anyhow::anyhow!("Cant change team while game running")),
         }
     }
 
     pub fn remove_player(&mut self, player_id: &str) -> Result<Player, anyhow::Error> {
-        log::info!("Removing player {}", player_id);
+        tracing::info!(player_id, "removing player");
         match self {
             RoomState::Lobby(data) => {
                 let p_index = data
@@ -214,6 +465,13 @@ impl RoomState {
                     .position(|p| p.player.id == player_id)
                     .ok_or_else(|| anyhow::anyhow!("Player doesnt exist"))?;
                 let player = data.players.remove(p_index);
+                if data.host == player_id {
+                    data.host = data
+                        .players
+                        .first()
+                        .map(|p| p.player.id.clone())
+                        .unwrap_or_default();
+                }
                 Ok(player.player)
             }
             RoomState::Game(data) => {
@@ -222,102 +480,272 @@ impl RoomState {
                     .iter()
                     .position(|p| p.player.id == player_id)
                     .ok_or_else(|| anyhow::anyhow!("Player doesnt exist"))?;
+                data.pass_turn_of(player_id);
                 let player = data.players.remove(p_index);
+                if data.host == player_id {
+                    data.host = data
+                        .players
+                        .first()
+                        .map(|p| p.player.id.clone())
+                        .unwrap_or_default();
+                }
                 Ok(player.player)
             }
         }
     }
+
+    pub fn set_player_ready(&mut self, player_id: &str, ready: bool) -> Result<(), anyhow::Error> {
+        match self {
+            RoomState::Lobby(data) => {
+                let pl = data
+                    .players
+                    .iter_mut()
+                    .find(|p| p.player.id == player_id)
+                    .ok_or_else(|| anyhow::anyhow!("Player does not exist"))?;
+                pl.ready = ready;
+                Ok(())
+            }
+            RoomState::Game(_) => Err(anyhow::anyhow!("Game already running")),
+        }
+    }
+
+    /// Replaces the lobby proposal. Everyone has to ready up again for the new game.
+    pub fn propose_game(
+        &mut self,
+        player_id: &str,
+        proposal: GameProposal,
+    ) -> Result<(), anyhow::Error> {
+        match self {
+            RoomState::Lobby(data) => {
+                if !data.players.iter().any(|p| p.player.id == player_id) {
+                    return Err(anyhow::anyhow!("Player does not exist"));
+                }
+                data.players.iter_mut().for_each(|p| p.ready = false);
+                data.proposal = Some(Box::new(proposal));
+                data.vote_proposal(player_id)
+            }
+            RoomState::Game(_) => Err(anyhow::anyhow!("Game already running")),
+        }
+    }
+
+    pub fn vote_proposal(&mut self, player_id: &str) -> Result<(), anyhow::Error> {
+        match self {
+            RoomState::Lobby(data) => data.vote_proposal(player_id),
+            RoomState::Game(_) => Err(anyhow::anyhow!("Game already running")),
+        }
+    }
 }
 
 #[derive(SimpleObject, Serialize, Clone)]
+#[graphql(complex)]
 pub struct LobbyData {
     pub players: Vec<LobbyPlayer>,
     pub last_game: Option<LastGame>,
-}
-#[derive(SimpleObject, Serialize, Clone)]
-pub struct LastGame {
-    last_game: GameData,
-    leader_board: Vec<Rank>,
+    /// Player who can confirm proposals alone. Passed on when they leave the room.
+    pub host: String,
+    pub proposal: Option<Box<GameProposal>>,
 }
 
-#[derive(SimpleObject, Serialize, Clone)]
-pub struct Rank {
-    pub rank: u32,
-    pub player: Player,
-}
+impl LobbyData {
+    fn vote_proposal(&mut self, player_id: &str) -> Result<(), anyhow::Error> {
+        let proposal = self
+            .proposal
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("No game proposed"))?;
+        if !proposal.votes.iter().any(|v| v == player_id) {
+            proposal.votes.push(player_id.into());
+        }
+        proposal.accepted = proposal.accepted
+            || player_id == self.host
+            || self
+                .players
+                .iter()
+                .filter(|p| p.connected)
+                .all(|p| proposal.votes.contains(&p.player.id));
+        Ok(())
+    }
 
-#[derive(Debug, SimpleObject, Serialize, Clone)]
-#[graphql(complex)]
-pub struct LobbyPlayer {
-    pub player: Player,
+    /// Returns why the proposed game cannot start yet, if anything stops it.
+    pub fn start_blocker(&self) -> Option<String> {
+        let proposal = match &self.proposal {
+            Some(proposal) => proposal,
+            None => return Some("No game proposed".into()),
+        };
+        if !proposal.accepted {
+            return Some("Proposal not accepted".into());
+        }
+        let range = proposal.game.player_range();
+        let connected = self.players.iter().filter(|p| p.connected).count();
+        if !range.contains(&connected) {
+            return Some(format!(
+                "Needs {} to {} players",
+                range.start(),
+                range.end()
+            ));
+        }
+        if self.players.iter().any(|p| p.connected && !p.ready) {
+            return Some("Not every player is ready".into());
+        }
+        None
+    }
 
-    #[serde(skip_serializing)]
-    #[graphql(skip)]
-    pub send_channel: Option<Sender<ServerResponse>>,
+    /// Checks that the accepted proposal is being started and nothing else blocks it.
+    pub fn can_start(&self, start: &StartMessages) -> Result<(), anyhow::Error> {
+        if let Some(proposal) = &self.proposal {
+            if !proposal.matches(start) {
+                return Err(anyhow::anyhow!("Game does not match proposal"));
+            }
+        }
+        match self.start_blocker() {
+            Some(reason) => Err(anyhow::anyhow!(reason)),
+            None => Ok(()),
+        }
+    }
 }
 
 #[ComplexObject]
-impl LobbyPlayer {
-    pub async fn is_connected<'ctx>(
-        &self,
-        _ctx: &Context<'_>,
-    ) -> Result<bool, async_graphql::Error> {
-        Ok(self.send_channel.is_some())
+impl LobbyData {
+    pub async fn start_status(&self) -> StartStatus {
+        let reason = self.start_blocker();
+        StartStatus {
+            can_start: reason.is_none(),
+            reason,
+        }
     }
 }
 
-impl BroadcastPlayers<GamePlayer> for GameData {
-    fn get_player(&self) -> &Vec<GamePlayer> {
-        &self.players
-    }
+#[derive(SimpleObject, Serialize, Clone)]
+pub struct StartStatus {
+    pub can_start: bool,
+    pub reason: Option<String>,
 }
-impl BroadcastPlayers<LobbyPlayer> for LobbyData {
-    fn get_player(&self) -> &Vec<LobbyPlayer> {
-        &self.players
-    }
+
+/// Game the lobby wants to play next, with the settings it should start with.
+#[derive(SimpleObject, Serialize, Clone)]
+pub struct GameProposal {
+    pub game: GameKind,
+    pub board_size: Option<u16>,
+    pub win_rules: Option<WinRules>,
+    pub caller_mode: Option<CallerMode>,
+    pub claim_penalty: Option<ClaimPenalty>,
+    /// Cards each Bingo player plays, one when unset.
+    pub cards: Option<u16>,
+    pub pool: Option<NumberPool>,
+    pub board_width: Option<u32>,
+    pub board_height: Option<u32>,
+    pub proposed_by: String,
+    pub votes: Vec<String>,
+    /// Set once the host or every connected player voted for it.
+    pub accepted: bool,
 }
 
-#[async_trait]
-trait BroadcastPlayers<T: ChannelPlayer + Send + Sync> {
-    fn get_player(&self) -> &Vec<T>;
+impl GameProposal {
+    /// Win rules for a Bingo proposal, checked against its board size once the size is
+    /// within the server's `limits`.
+    pub fn bingo_rules(
+        game: GameKind,
+        board_size: Option<u16>,
+        limits: BoardSizeLimits,
+        pattern: Option<WinPattern>,
+        mask: Option<Vec<Vec<bool>>>,
+        lines_to_win: Option<u32>,
+    ) -> Result<Option<WinRules>, anyhow::Error> {
+        match (game, board_size) {
+            (GameKind::Bingo, Some(board_size)) => {
+                limits.check(board_size)?;
+                Ok(Some(WinRules::new(
+                    pattern.unwrap_or_default(),
+                    mask,
+                    lines_to_win,
+                    board_size,
+                )?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Number pool for a Bingo proposal, checked against its board size once the size is
+    /// within the server's `limits`.
+    pub fn bingo_pool(
+        game: GameKind,
+        board_size: Option<u16>,
+        limits: BoardSizeLimits,
+        size: Option<u32>,
+        labels: Option<Vec<String>>,
+    ) -> Result<Option<NumberPool>, anyhow::Error> {
+        match (game, board_size) {
+            (GameKind::Bingo, Some(board_size)) => {
+                limits.check(board_size)?;
+                Ok(Some(NumberPool::new(size, labels, board_size)?))
+            }
+            _ => Ok(None),
+        }
+    }
 
-    async fn broadcast(&self, message: ServerResponse) {
-        let futures = self.get_player().iter().map(|f| f.send(message.clone()));
-        futures::future::join_all(futures).await;
+    /// Caller mode for a Bingo proposal that draws the numbers of its pool as balls, or for
+    /// Tambola.
+    pub fn proposed_caller_mode(
+        game: GameKind,
+        pool: Option<&NumberPool>,
+        balls: Option<u32>,
+        call_interval: Option<u64>,
+    ) -> Result<Option<CallerMode>, anyhow::Error> {
+        match (game, pool) {
+            (GameKind::Bingo, Some(pool)) => CallerMode::new(balls, call_interval, pool),
+            (GameKind::Tambola, _) => Ok(Some(TambolaStart::new(call_interval)?.caller_mode)),
+            _ => Ok(None),
+        }
     }
-}
 
-#[async_trait]
-impl ChannelPlayer for GamePlayer {
-    fn get_channel(&self) -> &Option<Sender<ServerResponse>> {
-        &self.send_channel
+    pub fn matches(&self, start: &StartMessages) -> bool {
+        match start {
+            StartMessages::BingoStart(data) => {
+                self.game == GameKind::Bingo
+                    && self.board_size == Some(data.board_size)
+                    && self.win_rules.as_ref() == Some(&data.win_rules)
+                    && self.caller_mode == data.caller_mode
+                    && self.claim_penalty == data.claim_penalty
+                    && self.cards.unwrap_or(1) == data.cards
+                    && self.pool.as_ref() == Some(&data.pool)
+            }
+            StartMessages::BoxesStart(data) => {
+                self.game == GameKind::Boxes
+                    && self.board_width == Some(data.board_width)
+                    && self.board_height == Some(data.board_height)
+            }
+            StartMessages::BluffStart(_) => self.game == GameKind::Bluff,
+            StartMessages::TambolaStart(data) => {
+                self.game == GameKind::Tambola
+                    && self.caller_mode.as_ref() == Some(&data.caller_mode)
+            }
+        }
     }
 }
-#[async_trait]
-impl ChannelPlayer for LobbyPlayer {
-    fn get_channel(&self) -> &Option<Sender<ServerResponse>> {
-        &self.send_channel
-    }
+#[derive(SimpleObject, Serialize, Clone)]
+pub struct LastGame {
+    last_game: GameData,
+    leader_board: Vec<Rank>,
+    team_leader_board: Vec<TeamRank>,
+    player_stats: Vec<PlayerStats>,
+    /// Length of the game in milliseconds.
+    duration: u64,
 }
 
-#[async_trait]
-trait ChannelPlayer {
-    fn get_channel(&self) -> &Option<Sender<ServerResponse>>;
+#[derive(Debug, SimpleObject, Serialize, Clone)]
+#[graphql(complex)]
+pub struct LobbyPlayer {
+    pub player: Player,
+    pub ready: bool,
 
-    async fn send(&self, message: ServerResponse) {
-        match self.get_channel() {
-            Some(channel) => match channel.send(message).await {
-                Ok(_) => {}
-                Err(_er) => {
-                    log::warn!("ERROR SENDING ")
-                }
-            },
-            None => {}
-        }
-    }
+    #[serde(skip_serializing)]
+    #[graphql(skip)]
+    pub connected: bool,
+}
 
-    fn has_channel(&self) -> bool {
-        self.get_channel().is_some()
+#[ComplexObject]
+impl LobbyPlayer {
+    pub async fn is_connected(&self, _ctx: &Context<'_>) -> Result<bool, async_graphql::Error> {
+        Ok(self.connected)
     }
 }
 
@@ -328,55 +756,172 @@ pub enum CommonPlayer {
     LobbyPlayer(LobbyPlayer),
 }
 
-#[derive(SimpleObject, Serialize, Clone, Debug)]
-pub struct Player {
-    pub id: String,
-    pub name: String,
-}
-
 #[derive(SimpleObject, Serialize, Clone)]
 pub struct PlayerJoined {
     pub player: Player,
     pub room: Room,
+    #[serde(skip_serializing)]
+    pub seq: u64,
 }
 
 #[derive(SimpleObject, Serialize, Clone)]
 pub struct PlayerLeft {
     pub player: Player,
     pub room: Room,
+    #[serde(skip_serializing)]
+    pub seq: u64,
 }
 
 #[derive(SimpleObject, Serialize, Clone)]
 pub struct PlayerConnected {
     pub player: Player,
     pub room: Room,
+    #[serde(skip_serializing)]
+    pub seq: u64,
 }
 
 #[derive(SimpleObject, Serialize, Clone)]
 pub struct PlayerRemoved {
     pub player: Player,
     pub room: Room,
+    #[serde(skip_serializing)]
+    pub seq: u64,
+}
+
+#[derive(SimpleObject, Serialize, Clone)]
+pub struct LobbyUpdate {
+    pub room: Room,
+    #[serde(skip_serializing)]
+    pub seq: u64,
+}
+
+#[derive(SimpleObject, Serialize, Clone)]
+pub struct TeamChanged {
+    pub player: Player,
+    pub room: Room,
+    #[serde(skip_serializing)]
+    pub seq: u64,
+}
+
+/// A vote to undo the last move. `accepted` is set once the move has been reverted.
+#[derive(SimpleObject, Serialize, Clone)]
+pub struct UndoVote {
+    pub player: Player,
+    pub accepted: bool,
+    pub room: Room,
+    #[serde(skip_serializing)]
+    pub seq: u64,
 }
 
+/// Sent instead of a replay when the events a client missed are no longer kept. The client
+/// should replace its state with `room`.
 #[derive(SimpleObject, Serialize, Clone)]
-pub struct ChatMessage{
-    pub player:Player,
-    pub message:String,
+pub struct ResyncRequired {
+    pub room: Room,
+    #[serde(skip_serializing)]
+    pub seq: u64,
 }
 
+#[derive(SimpleObject, Serialize, Clone)]
+pub struct Announcement {
+    pub message: String,
+    #[serde(skip_serializing)]
+    pub seq: u64,
+}
+
+#[derive(SimpleObject, Serialize, Clone)]
+pub struct RoomClosed {
+    pub room_id: String,
+    pub reason: String,
+    #[serde(skip_serializing)]
+    pub seq: u64,
+}
+
+#[derive(SimpleObject, Serialize, Clone)]
+pub struct ChatMessage {
+    pub player: Player,
+    pub message: String,
+    #[serde(skip_serializing)]
+    pub seq: u64,
+}
+
+/// Events sent to the players of a room. Each one carries a `seq` numbering it in its room,
+/// counting up by one; a `ResyncRequired` takes the number of the last event it replaces.
+#[allow(clippy::large_enum_variant)]
 #[derive(Serialize, Union, Clone)]
 pub enum ServerResponse {
     PlayerJoined(PlayerJoined),
     PlayerConnected(PlayerConnected),
     PlayerLeft(PlayerLeft),
     PlayerRemoved(PlayerRemoved),
+    TeamChanged(TeamChanged),
+    LobbyUpdate(LobbyUpdate),
 
     GameMessage(GameMessage),
     ChatMessage(ChatMessage),
+    UndoVote(UndoVote),
+
+    Announcement(Announcement),
+    RoomClosed(RoomClosed),
+    ResyncRequired(ResyncRequired),
+}
+
+/// An event numbered by the order it was sent in its room.
+#[derive(Serialize, Clone)]
+pub struct RoomEvent {
+    pub seq: u64,
+    pub event: ServerResponse,
+}
+
+impl RoomEvent {
+    /// Numbers the event, setting the `seq` it carries as well.
+    pub fn new(seq: u64, mut event: ServerResponse) -> Self {
+        *event.seq_mut() = seq;
+        Self { seq, event }
+    }
+}
+
+impl ServerResponse {
+    fn seq_mut(&mut self) -> &mut u64 {
+        match self {
+            ServerResponse::PlayerJoined(e) => &mut e.seq,
+            ServerResponse::PlayerConnected(e) => &mut e.seq,
+            ServerResponse::PlayerLeft(e) => &mut e.seq,
+            ServerResponse::PlayerRemoved(e) => &mut e.seq,
+            ServerResponse::TeamChanged(e) => &mut e.seq,
+            ServerResponse::LobbyUpdate(e) => &mut e.seq,
+            ServerResponse::GameMessage(e) => &mut e.seq,
+            ServerResponse::ChatMessage(e) => &mut e.seq,
+            ServerResponse::UndoVote(e) => &mut e.seq,
+            ServerResponse::Announcement(e) => &mut e.seq,
+            ServerResponse::RoomClosed(e) => &mut e.seq,
+            ServerResponse::ResyncRequired(e) => &mut e.seq,
+        }
+    }
+
+    /// Name of the event, used to tag log output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ServerResponse::PlayerJoined(_) => "player_joined",
+            ServerResponse::PlayerConnected(_) => "player_connected",
+            ServerResponse::PlayerLeft(_) => "player_left",
+            ServerResponse::PlayerRemoved(_) => "player_removed",
+            ServerResponse::TeamChanged(_) => "team_changed",
+            ServerResponse::LobbyUpdate(_) => "lobby_update",
+            ServerResponse::GameMessage(_) => "game_message",
+            ServerResponse::ChatMessage(_) => "chat_message",
+            ServerResponse::UndoVote(_) => "undo_vote",
+            ServerResponse::Announcement(_) => "announcement",
+            ServerResponse::RoomClosed(_) => "room_closed",
+            ServerResponse::ResyncRequired(_) => "resync_required",
+        }
+    }
 }
 
 #[derive(SimpleObject, Serialize, Clone)]
 pub struct GameMessage {
     pub event: GameEvents,
     pub room: Room,
+    #[serde(skip_serializing)]
+    pub seq: u64,
 }
@@ -1,68 +1,100 @@
-use std::collections::HashSet;
+use std::{cmp::Reverse, collections::HashSet};
 
-use async_graphql::{ComplexObject, Context, Object, Result, SimpleObject, Union};
+#[cfg(feature = "graphql")]
+use async_graphql::{ComplexObject, Context, Enum, SimpleObject, Union};
 
-use ndarray::{Array2, Axis};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{
-    data::{GameMessage, Player, Rank, ServerResponse, Storage},
-    logic::{GameEvents, GamePlayer, GameStarted, PlayerEvents, RoomUpdate},
-};
+use crate::utils::now_millis;
 
-use super::{GameTrait, PlayerMessages, StartMessages};
+use super::{GamePlayer, GameTrait, Player, Rank};
 
-#[derive(Clone, Serialize, SimpleObject)]
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
 pub struct Bingo {
     pub game_state: GameState,
     pub board_size: u16,
+    pub win_rules: WinRules,
+    /// Set when the server draws the numbers instead of the players.
+    pub caller_mode: Option<CallerMode>,
+    /// Set when players have to claim their win in turn mode, with what a false claim costs.
+    pub claim_penalty: Option<ClaimPenalty>,
+    /// Cards each player plays at once.
+    pub cards: u16,
+    pub pool: NumberPool,
+    /// Numbers of the pool not called yet.
+    pub remaining: Vec<Cell>,
+    #[serde(skip)]
+    #[cfg_attr(feature = "graphql", graphql(skip))]
+    pattern_index: PatternIndex,
+    /// Numbers called when a claim last came in or cost a player their turn. Undo stops
+    /// there, since the claim was judged on them.
+    #[serde(skip)]
+    #[cfg_attr(feature = "graphql", graphql(skip))]
+    claimed_at: usize,
 }
 
 pub enum BingoPlayerMessages {
-    ReadyBoard(Board),
+    /// Readies the player's cards, one board for each.
+    ReadyBoard(Vec<Board>),
     Move(Cell),
+    /// Draws the next number in caller mode.
+    CallNext,
+    /// Claims a completed board, in caller mode or when the game takes claims.
+    ClaimBingo,
 }
 
 pub struct BingoStart {
     pub board_size: u16,
+    /// Milliseconds players get to ready their board before the rest are filled in.
+    pub board_time_limit: Option<u64>,
+    pub win_rules: WinRules,
+    pub caller_mode: Option<CallerMode>,
+    pub claim_penalty: Option<ClaimPenalty>,
+    pub cards: u16,
+    pub pool: NumberPool,
 }
 
 impl GameTrait for Bingo {
     type PlayerMessage = BingoPlayerMessages;
     type StartMessage = BingoStart;
     type PlayerGameData = BingoPlayerData;
-    type InputHandler = BingoInputs;
+
+    fn player_range() -> std::ops::RangeInclusive<usize> {
+        2..=16
+    }
 
     fn is_game_running(&self) -> bool {
         self.game_state.is_game_running()
     }
     fn can_change_turn(&self, player_id: &str) -> bool {
         match &self.game_state {
-            GameState::BoardCreation(_) => false,
+            GameState::BoardCreation(_) | GameState::Calling(_) => false,
             GameState::GameRunning(data) => data.turn == player_id,
         }
     }
 
-    fn get_rankings(&self, players: &[GamePlayer]) -> Vec<crate::data::Rank> {
+    fn get_rankings(&self, players: &[GamePlayer]) -> Vec<Rank> {
         match &self.game_state {
             GameState::BoardCreation(_) => vec![],
-            GameState::GameRunning(data) => {
-                let mut player_turn = players.iter().map(|p| (0, 0, p)).collect::<Vec<_>>();
-                for l in 0..data.selected_numbers.len() {
-                    let temp_selected_cells = &data.selected_numbers[0..l + 1];
-                    players.iter().enumerate().for_each(|(player_index, p)| {
-                        let score = p
+            GameState::Calling(data) => {
+                self.claim_rankings(&data.winners, &data.disqualified, players)
+            }
+            GameState::GameRunning(data) if self.claim_penalty.is_some() => {
+                self.claim_rankings(&data.winners, &data.disqualified, players)
+            }
+            GameState::GameRunning(_) => {
+                // Best score up to the lines to win, and the call that reached it
+                let mut player_turn = players
+                    .iter()
+                    .map(|p| {
+                        let (score, scored_at) = p
                             .data
                             .as_bingo_player_data()
-                            .and_then(|b| b.board.as_ref())
-                            .map(|board| board.get_score(temp_selected_cells))
-                            .unwrap_or(0)
-                            .clamp(0, self.board_size as u32);
-                        if player_turn[player_index].0 < score {
-                            player_turn[player_index] = (score, l, player_turn[player_index].2);
-                        }
-                    });
-                }
+                            .map_or((0, 0), |b| b.standing(self.win_rules.lines_to_win));
+                        (score, scored_at, p)
+                    })
+                    .collect::<Vec<_>>();
                 player_turn.sort_by(|p1, p2| {
                     if p1.0.cmp(&p2.0).is_eq() {
                         p1.1.cmp(&p2.1)
@@ -97,14 +129,8 @@ impl GameTrait for Bingo {
         if let GameState::GameRunning(data) = &self.game_state {
             if !players
                 .iter()
-                .filter(|p| p.send_channel.is_some())
-                .any(|p| {
-                    p.data
-                        .as_bingo_player_data()
-                        .and_then(|b| b.board.as_ref())
-                        .map(|b| !b.has_completed(&data.selected_numbers))
-                        .unwrap_or(false)
-                })
+                .filter(|p| p.connected)
+                .any(|p| !self.is_done(players, &p.player, data))
             {
                 return None;
             }
@@ -113,14 +139,7 @@ impl GameTrait for Bingo {
             if let Some(position) = current_player_position {
                 cycle_iter.nth(position);
                 for player in cycle_iter {
-                    if player.send_channel.is_some()
-                        && player
-                            .data
-                            .as_bingo_player_data()
-                            .and_then(|b| b.board.as_ref())
-                            .map(|b| !b.has_completed(&data.selected_numbers))
-                            .unwrap_or(false)
-                    {
+                    if player.connected && !self.is_done(players, &player.player, data) {
                         return Some(player.player.id.clone());
                     }
                 }
@@ -133,11 +152,40 @@ impl GameTrait for Bingo {
 
     fn change_turn(&mut self, player_id: &str) {
         match &mut self.game_state {
-            GameState::BoardCreation(_) => {}
+            GameState::BoardCreation(_) | GameState::Calling(_) => {}
             GameState::GameRunning(data) => data.turn = player_id.into(),
         }
     }
 
+    fn undo_last_move(
+        &mut self,
+        players: &mut [GamePlayer],
+    ) -> std::result::Result<String, anyhow::Error> {
+        if self.claimed_at > 0 && self.game_state.called().len() <= self.claimed_at {
+            return Err(anyhow::anyhow!("Cannot undo past a Bingo claim"));
+        }
+        let selected_by = match &mut self.game_state {
+            GameState::BoardCreation(_) => return Err(anyhow::anyhow!("Game Not Running")),
+            GameState::Calling(data) => {
+                data.called
+                    .pop()
+                    .ok_or_else(|| anyhow::anyhow!("No move to undo"))?
+                    .selected_by
+            }
+            GameState::GameRunning(data) => {
+                let last = data
+                    .selected_numbers
+                    .pop()
+                    .ok_or_else(|| anyhow::anyhow!("No move to undo"))?;
+                data.turn = last.selected_by.clone();
+                last.selected_by
+            }
+        };
+        self.replay_marks(players);
+        self.remaining = self.pool.remaining(self.game_state.called());
+        Ok(selected_by)
+    }
+
     fn handle_player_message(
         &mut self,
         player_id: &str,
@@ -145,94 +193,208 @@ impl GameTrait for Bingo {
         message: Self::PlayerMessage,
     ) -> std::result::Result<(), anyhow::Error> {
         match message {
-            BingoPlayerMessages::ReadyBoard(board) => match &mut self.game_state {
+            BingoPlayerMessages::ReadyBoard(mut boards) => match &mut self.game_state {
                 GameState::BoardCreation(board_creation) => {
                     if board_creation.ready.contains(&player_id.to_string()) {
                         Err(anyhow::anyhow!("Board already set"))
+                    } else if boards.len() != self.cards as usize {
+                        Err(anyhow::anyhow!(
+                            "Expected {} boards, got {}",
+                            self.cards,
+                            boards.len()
+                        ))
                     } else if let Some(player) =
                         players.iter_mut().find(|p| p.player.id == player_id)
                     {
                         if let Some(d) = player.data.as_bingo_player_data_mut() {
-                            d.board = Some(board);
+                            for board in boards.iter_mut() {
+                                board.reset_marks(&self.pattern_index);
+                            }
+                            d.boards = boards;
                         }
 
                         board_creation.ready.push(player_id.into());
-                        if board_creation.ready.len() == players.len() {
-                            use rand::seq::SliceRandom;
-                            self.game_state = GameState::GameRunning(GameRunning {
-                                turn: players
-                                    .choose(&mut rand::thread_rng())
-                                    .ok_or_else(|| anyhow::anyhow!("No player"))?
-                                    .player
-                                    .id
-                                    .clone(),
-                                selected_numbers: vec![],
-                            });
-                        }
-                        Ok(())
+                        self.start_when_ready(players)
                     } else {
                         Err(anyhow::anyhow!("Player not found"))
                     }
                 }
-                GameState::GameRunning(_) => Err(anyhow::anyhow!("Game Already Running")),
+                GameState::GameRunning(_) | GameState::Calling(_) => {
+                    Err(anyhow::anyhow!("Game Already Running"))
+                }
             },
             BingoPlayerMessages::Move(mov) => match &mut self.game_state {
                 GameState::BoardCreation(_) => Err(anyhow::anyhow!("Game Not Running")),
+                GameState::Calling(_) => Err(anyhow::anyhow!("Numbers are drawn by the caller")),
                 GameState::GameRunning(running_data) => {
                     if running_data.turn == player_id {
-                        if running_data
-                            .selected_numbers
-                            .iter()
-                            .any(|c| c.cell_value == mov)
-                        {
-                            return Err(anyhow::anyhow!("Invalid move"));
-                        } else {
-                            running_data.selected_numbers.push(SelectedCell {
-                                selected_by: player_id.into(),
-                                cell_value: mov,
-                            });
-                            if let Some(player) = self.get_next_turn_player(players) {
-                                self.change_turn(&player)
-                            }
-                        }
+                        self.pool.check(mov)?;
+                        running_data.check_call(mov)?;
+                        running_data.selected_numbers.push(SelectedCell {
+                            selected_by: player_id.into(),
+                            cell_value: mov,
+                            selected_at: now_millis(),
+                        });
+                        self.mark_last_call(players);
+                        self.pass_turn(players);
                         Ok(())
                     } else {
                         Err(anyhow::anyhow!("Not your turn"))
                     }
                 }
             },
+            BingoPlayerMessages::CallNext => match &mut self.game_state {
+                GameState::Calling(calling) => {
+                    if calling.caller != player_id {
+                        Err(anyhow::anyhow!("Only {} can call numbers", calling.caller))
+                    } else if calling.next_call_at.is_some() {
+                        Err(anyhow::anyhow!("Numbers are drawn on a timer"))
+                    } else {
+                        calling.draw(player_id, self.caller_mode.as_ref())?;
+                        self.mark_last_call(players);
+                        Ok(())
+                    }
+                }
+                _ => Err(anyhow::anyhow!("Not in caller mode")),
+            },
+            BingoPlayerMessages::ClaimBingo => match &mut self.game_state {
+                GameState::Calling(calling) => {
+                    if calling.winners.iter().any(|p| p == player_id) {
+                        return Err(anyhow::anyhow!("Bingo already claimed"));
+                    }
+                    if calling.disqualified.iter().any(|p| p == player_id) {
+                        return Err(anyhow::anyhow!("Disqualified after a false claim"));
+                    }
+                    let completed =
+                        Self::has_completed(players, player_id, self.win_rules.lines_to_win)?;
+                    if completed {
+                        calling.winners.push(player_id.into());
+                    } else {
+                        calling.disqualified.push(player_id.into());
+                    }
+                    self.claimed_at = calling.called.len();
+                    Ok(())
+                }
+                GameState::GameRunning(running) => {
+                    let penalty = self
+                        .claim_penalty
+                        .ok_or_else(|| anyhow::anyhow!("Wins are counted without claims"))?;
+                    if running.winners.iter().any(|p| p == player_id) {
+                        return Err(anyhow::anyhow!("Bingo already claimed"));
+                    }
+                    if running.disqualified.iter().any(|p| p == player_id) {
+                        return Err(anyhow::anyhow!("Disqualified after a false claim"));
+                    }
+                    let completed =
+                        Self::has_completed(players, player_id, self.win_rules.lines_to_win)?;
+                    let has_turn = running.turn == player_id;
+                    match (completed, penalty) {
+                        (true, _) => running.winners.push(player_id.into()),
+                        (false, ClaimPenalty::Disqualify) => {
+                            running.disqualified.push(player_id.into())
+                        }
+                        // A player on turn loses this one, anyone else their next
+                        (false, ClaimPenalty::LoseTurn) if !has_turn => {
+                            running.skip_turn.push(player_id.into())
+                        }
+                        (false, ClaimPenalty::LoseTurn) => {}
+                    }
+                    self.claimed_at = running.selected_numbers.len();
+                    if has_turn {
+                        self.pass_turn(players);
+                    }
+                    Ok(())
+                }
+                GameState::BoardCreation(_) => Err(anyhow::anyhow!("Game Not Running")),
+            },
         }
     }
 
     fn is_game_end(&self, players: &[GamePlayer]) -> bool {
-        if let Some(game_running) = self.game_state.as_game_running() {
-            let online_players = players.iter().filter(|p| p.send_channel.is_some());
-            online_players
-                .filter(|p| {
-                    p.data
-                        .as_bingo_player_data()
-                        .and_then(|b| b.board.as_ref())
-                        .map(|board| {
-                            board.get_score(&game_running.selected_numbers) < self.board_size as u32
-                        })
-                        .unwrap_or(false)
-                })
-                .count()
-                <= 1
+        if let GameState::Calling(calling) = &self.game_state {
+            let balls = self.caller_mode.as_ref().map_or(0, |mode| mode.balls);
+            !calling.winners.is_empty()
+                || calling.called.len() >= balls as usize
+                || players
+                    .iter()
+                    .filter(|p| p.connected && !calling.disqualified.contains(&p.player.id))
+                    .count()
+                    <= 1
+        } else if let Some(game_running) = self.game_state.as_game_running() {
+            let pending_players = players
+                .iter()
+                .filter(|p| p.connected && !self.is_done(players, &p.player, game_running));
+            Player::sides(pending_players.map(|p| &p.player)).len() <= 1
         } else {
-            players.iter().filter(|p| p.send_channel.is_some()).count() <= 1
+            players.iter().filter(|p| p.connected).count() <= 1
         }
     }
 
-    fn start_game(data: Self::StartMessage, _: &[GamePlayer], _: &str) -> Bingo {
-        Self {
-            board_size: data.board_size,
-            game_state: GameState::BoardCreation(BoardCreation { ready: vec![] }),
+    fn deadline(&self) -> Option<u64> {
+        match &self.game_state {
+            GameState::BoardCreation(data) => data.deadline,
+            GameState::Calling(data) => data.next_call_at,
+            GameState::GameRunning(_) => None,
         }
     }
 
-    fn input_handler(room_id: String, player_id: String) -> Self::InputHandler {
-        BingoInputs { room_id, player_id }
+    fn extend_deadline(&mut self, by: u64) {
+        match &mut self.game_state {
+            GameState::BoardCreation(data) => data.deadline = data.deadline.map(|d| d + by),
+            GameState::Calling(data) => data.next_call_at = data.next_call_at.map(|d| d + by),
+            GameState::GameRunning(_) => {}
+        }
+    }
+
+    /// Gives a random board to everyone who is not ready yet and starts calling numbers.
+    /// In caller mode, draws the next number once it is due.
+    fn handle_deadline(&mut self, players: &mut [GamePlayer]) -> Result<(), anyhow::Error> {
+        let (pool, board_size) = (self.number_pool(), self.board_size);
+        match &mut self.game_state {
+            GameState::BoardCreation(board_creation) => {
+                for player in players.iter_mut() {
+                    if board_creation.ready.contains(&player.player.id) {
+                        continue;
+                    }
+                    if let Some(d) = player.data.as_bingo_player_data_mut() {
+                        d.boards = (0..self.cards)
+                            .map(|_| Board::random(board_size, pool))
+                            .collect::<Result<_, _>>()?;
+                        for board in d.boards.iter_mut() {
+                            board.reset_marks(&self.pattern_index);
+                        }
+                    }
+                    board_creation.ready.push(player.player.id.clone());
+                }
+                self.start_when_ready(players)?;
+            }
+            GameState::Calling(calling) => {
+                calling.draw("", self.caller_mode.as_ref())?;
+                self.mark_last_call(players);
+            }
+            GameState::GameRunning(_) => {}
+        }
+        Ok(())
+    }
+
+    fn start_game(data: Self::StartMessage, _: &[GamePlayer], player_id: &str) -> Bingo {
+        let pattern_index = PatternIndex::new(&data.win_rules, data.board_size);
+        Self {
+            board_size: data.board_size,
+            win_rules: data.win_rules,
+            caller_mode: data.caller_mode,
+            claim_penalty: data.claim_penalty,
+            cards: data.cards,
+            remaining: (1..=data.pool.size).collect(),
+            pool: data.pool,
+            pattern_index,
+            claimed_at: 0,
+            game_state: GameState::BoardCreation(BoardCreation {
+                ready: vec![],
+                deadline: data.board_time_limit.map(|limit| now_millis() + limit),
+                caller: player_id.into(),
+            }),
+        }
     }
 
     fn create_player_data(
@@ -240,27 +402,254 @@ impl GameTrait for Bingo {
         _players: &[Player],
         _player_id: &str,
     ) -> Self::PlayerGameData {
-        BingoPlayerData { board: None }
+        BingoPlayerData { boards: vec![] }
+    }
+}
+
+impl Bingo {
+    /// Largest number a board may hold.
+    pub fn number_pool(&self) -> Cell {
+        self.pool.size
+    }
+
+    /// Most cards a player may play at once.
+    pub const MAX_CARDS: u16 = 4;
+
+    /// Checks the cards each player plays, one by default.
+    pub fn check_cards(cards: Option<u16>) -> Result<u16, anyhow::Error> {
+        let cards = cards.unwrap_or(1);
+        if !(1..=Self::MAX_CARDS).contains(&cards) {
+            return Err(anyhow::anyhow!(
+                "Cards must be between 1 and {}",
+                Self::MAX_CARDS
+            ));
+        }
+        Ok(cards)
+    }
+
+    /// Checks a board sent by a player, filling in its empty cells if `randomize` is set.
+    pub fn make_board(
+        &self,
+        numbers: Vec<Vec<Cell>>,
+        randomize: bool,
+    ) -> Result<Board, anyhow::Error> {
+        if randomize {
+            Board::fill(numbers, self.board_size, self.number_pool())
+        } else {
+            Board::with_pool(numbers, self.board_size, self.number_pool())
+        }
+    }
+
+    /// Checks the boards sent for a player's cards. With `randomize` set, their empty
+    /// cells are filled in and missing cards get a random board.
+    pub fn make_boards(
+        &self,
+        mut boards: Vec<Vec<Vec<Cell>>>,
+        randomize: bool,
+    ) -> Result<Vec<Board>, anyhow::Error> {
+        let cards = self.cards as usize;
+        if randomize && boards.len() < cards {
+            boards.resize(cards, vec![]);
+        }
+        if boards.len() != cards {
+            return Err(anyhow::anyhow!(
+                "Expected {} boards, got {}",
+                cards,
+                boards.len()
+            ));
+        }
+        boards
+            .into_iter()
+            .map(|numbers| self.make_board(numbers, randomize))
+            .collect()
+    }
+
+    /// Starts calling numbers once every player readied a board.
+    fn start_when_ready(&mut self, players: &[GamePlayer]) -> Result<(), anyhow::Error> {
+        if let GameState::BoardCreation(board_creation) = &self.game_state {
+            if board_creation.ready.len() != players.len() {
+                return Ok(());
+            }
+            if let Some(mode) = &self.caller_mode {
+                self.game_state = GameState::Calling(Calling {
+                    caller: board_creation.caller.clone(),
+                    called: vec![],
+                    next_call_at: mode.call_interval.map(|interval| now_millis() + interval),
+                    winners: vec![],
+                    disqualified: vec![],
+                });
+            } else {
+                use rand::seq::SliceRandom;
+                self.game_state = GameState::GameRunning(GameRunning {
+                    turn: players
+                        .choose(&mut rand::thread_rng())
+                        .ok_or_else(|| anyhow::anyhow!("No player"))?
+                        .player
+                        .id
+                        .clone(),
+                    selected_numbers: vec![],
+                    winners: vec![],
+                    disqualified: vec![],
+                    skip_turn: vec![],
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks the number called last on every board and takes it out of the remaining
+    /// pool.
+    fn mark_last_call(&mut self, players: &mut [GamePlayer]) {
+        let number = match self.game_state.called().last() {
+            Some(last) => last.cell_value,
+            None => return,
+        };
+        self.remaining.retain(|n| *n != number);
+        let boards = players
+            .iter_mut()
+            .filter_map(|p| p.data.as_bingo_player_data_mut())
+            .flat_map(|d| d.boards.iter_mut());
+        for board in boards {
+            board.mark(&self.pattern_index, number, self.win_rules.lines_to_win);
+        }
+    }
+
+    /// Marks every board again from the numbers called so far, after one was taken back.
+    fn replay_marks(&self, players: &mut [GamePlayer]) {
+        let boards = players
+            .iter_mut()
+            .filter_map(|p| p.data.as_bingo_player_data_mut())
+            .flat_map(|d| d.boards.iter_mut());
+        for board in boards {
+            board.reset_marks(&self.pattern_index);
+            for cell in self.game_state.called() {
+                board.mark(
+                    &self.pattern_index,
+                    cell.cell_value,
+                    self.win_rules.lines_to_win,
+                );
+            }
+        }
+    }
+
+    /// Returns `true` if any of the player's cards completed `lines_to_win` patterns.
+    fn has_completed(
+        players: &[GamePlayer],
+        player_id: &str,
+        lines_to_win: u32,
+    ) -> Result<bool, anyhow::Error> {
+        let data = players
+            .iter()
+            .find(|p| p.player.id == player_id)
+            .and_then(|p| p.data.as_bingo_player_data())
+            .filter(|b| !b.boards.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Player not found"))?;
+        Ok(data.score() >= lines_to_win)
+    }
+
+    /// Gives the turn to the next player, skipping anyone who lost their turn to a false
+    /// claim.
+    fn pass_turn(&mut self, players: &[GamePlayer]) {
+        while let Some(player) = self.get_next_turn_player(players) {
+            self.change_turn(&player);
+            match &mut self.game_state {
+                GameState::GameRunning(data) if data.skip_turn.contains(&player) => {
+                    data.skip_turn.retain(|p| p != &player);
+                    self.claimed_at = data.selected_numbers.len();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Winners in claim order, then everyone else by the patterns their best card
+    /// completed, then the disqualified players.
+    fn claim_rankings(
+        &self,
+        winners: &[String],
+        disqualified: &[String],
+        players: &[GamePlayer],
+    ) -> Vec<Rank> {
+        let score = |p: &GamePlayer| p.data.as_bingo_player_data().map_or(0, |b| b.score());
+        let mut rest = players
+            .iter()
+            .filter(|p| !winners.contains(&p.player.id))
+            .map(|p| (disqualified.contains(&p.player.id), score(p), p))
+            .collect::<Vec<_>>();
+        rest.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+        let mut ranks = winners
+            .iter()
+            .filter_map(|id| players.iter().find(|p| &p.player.id == id))
+            .enumerate()
+            .map(|(i, p)| Rank {
+                rank: i as u32 + 1,
+                player: p.player.clone(),
+            })
+            .collect::<Vec<_>>();
+        let mut rank = ranks.len() as u32;
+        let mut last = None;
+        for (disqualified, score, p) in rest {
+            if last != Some((disqualified, score)) {
+                rank += 1;
+                last = Some((disqualified, score));
+            }
+            ranks.push(Rank {
+                rank,
+                player: p.player.clone(),
+            });
+        }
+        ranks
+    }
+
+    /// Returns `true` if the player takes no more turns. Without claims that is once their
+    /// side completed a board. With claims it is once their side claimed a win, or they
+    /// were disqualified.
+    fn is_done(&self, players: &[GamePlayer], player: &Player, data: &GameRunning) -> bool {
+        if self.claim_penalty.is_none() {
+            return self.has_side_completed(players, player);
+        }
+        data.disqualified.contains(&player.id)
+            || players
+                .iter()
+                .any(|p| p.player.is_teammate(player) && data.winners.contains(&p.player.id))
+    }
+
+    /// Returns `true` if the player, or any of their teammates, has completed a card.
+    fn has_side_completed(&self, players: &[GamePlayer], player: &Player) -> bool {
+        players
+            .iter()
+            .filter(|p| p.player.is_teammate(player))
+            .any(|p| {
+                p.data
+                    .as_bingo_player_data()
+                    .is_some_and(|b| b.score() >= self.win_rules.lines_to_win)
+            })
     }
 }
 
-#[derive(Serialize, SimpleObject, Clone)]
+#[derive(Serialize, Clone)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
 pub struct SelectedCell {
-    cell_value: u32,
-    selected_by: String,
+    pub cell_value: u32,
+    pub selected_by: String,
+    /// Milliseconds since the epoch when the number was called.
+    pub selected_at: u64,
 }
 pub type Cell = u32;
 
-#[derive(Serialize, Union, Clone)]
+#[derive(Serialize, Clone)]
+#[cfg_attr(feature = "graphql", derive(Union))]
 pub enum GameState {
     BoardCreation(BoardCreation),
     GameRunning(GameRunning),
+    Calling(Calling),
 }
 
 impl GameState {
-    /// Returns `true` if the game_state is [`GameRunning`].
+    /// Returns `true` once numbers are being called, by the players or the caller.
     pub fn is_game_running(&self) -> bool {
-        matches!(self, Self::GameRunning(..))
+        matches!(self, Self::GameRunning(..) | Self::Calling(..))
     }
 
     pub fn as_game_running(&self) -> Option<&GameRunning> {
@@ -270,225 +659,779 @@ impl GameState {
             None
         }
     }
+
+    /// Numbers called so far, in order.
+    pub fn called(&self) -> &[SelectedCell] {
+        match self {
+            Self::BoardCreation(_) => &[],
+            Self::GameRunning(data) => &data.selected_numbers,
+            Self::Calling(data) => &data.called,
+        }
+    }
 }
 
-#[derive(SimpleObject, Serialize, Clone)]
+#[derive(Serialize, Clone)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
 pub struct BoardCreation {
     ready: Vec<String>,
+    /// Milliseconds since the epoch when boards not readied yet are filled in randomly.
+    deadline: Option<u64>,
+    /// Player who started the game, and draws the numbers in caller mode without a timer.
+    caller: String,
 }
 
-#[derive(SimpleObject, Serialize, Clone)]
+#[derive(Serialize, Clone)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
 pub struct GameRunning {
     pub turn: String,
     pub selected_numbers: Vec<SelectedCell>,
+    /// Players whose claim was right, in claim order, when the game takes claims.
+    pub winners: Vec<String>,
+    /// Players out of the game after a false claim.
+    pub disqualified: Vec<String>,
+    /// Players who skip their next turn after a false claim.
+    pub skip_turn: Vec<String>,
+}
+
+/// Caller mode, where numbers are drawn from the pool and players claim their wins.
+#[derive(Serialize, Clone)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct Calling {
+    pub caller: String,
+    pub called: Vec<SelectedCell>,
+    /// Milliseconds since the epoch of the next draw, when numbers are drawn on a timer.
+    pub next_call_at: Option<u64>,
+    /// Players whose claim was right, in claim order.
+    pub winners: Vec<String>,
+    /// Players out of the game after a false claim.
+    pub disqualified: Vec<String>,
 }
 
-#[derive(SimpleObject, Serialize, Clone)]
-#[graphql(complex)]
+impl Calling {
+    /// Draws the next number, if the game is in caller mode.
+    fn draw(&mut self, player_id: &str, mode: Option<&CallerMode>) -> Result<(), anyhow::Error> {
+        let mode = mode.ok_or_else(|| anyhow::anyhow!("Not in caller mode"))?;
+        self.next_call_at = mode.draw(&mut self.called, player_id)?;
+        Ok(())
+    }
+}
+
+/// What a false Bingo claim costs in turn mode. Caller mode always disqualifies.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "graphql", derive(Enum))]
+#[serde(rename_all = "camelCase")]
+pub enum ClaimPenalty {
+    LoseTurn,
+    Disqualify,
+}
+
+impl ClaimPenalty {
+    /// Fails for a penalty the game mode cannot apply.
+    pub fn check(
+        penalty: Option<Self>,
+        caller_mode: Option<&CallerMode>,
+    ) -> Result<(), anyhow::Error> {
+        if caller_mode.is_some() && penalty == Some(Self::LoseTurn) {
+            Err(anyhow::anyhow!("Caller mode has no turns to lose"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Settings of caller mode.
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct CallerMode {
+    /// Numbers drawn from, the whole number pool. Classic games use 75 or 90.
+    pub balls: u32,
+    /// Milliseconds between draws. Without it the caller draws each number.
+    pub call_interval: Option<u64>,
+}
+
+impl CallerMode {
+    /// Shortest time between timed draws, in milliseconds.
+    pub const MIN_CALL_INTERVAL: u64 = 1000;
+
+    /// Caller mode is on when `balls` is given, with one ball for every number of the
+    /// `pool`.
+    pub fn new(
+        balls: Option<u32>,
+        call_interval: Option<u64>,
+        pool: &NumberPool,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        let balls = match balls {
+            Some(balls) => balls,
+            None if call_interval.is_some() => {
+                return Err(anyhow::anyhow!("A call interval needs caller mode"))
+            }
+            None => return Ok(None),
+        };
+        if balls != pool.size {
+            return Err(anyhow::anyhow!(
+                "Caller mode needs {} balls, one for each number of the pool",
+                pool.size
+            ));
+        }
+        Ok(Some(Self::with_balls(balls, call_interval)?))
+    }
+
+    /// Caller mode drawing from a fixed pool of `balls`, checking only the call interval.
+    pub fn with_balls(balls: u32, call_interval: Option<u64>) -> Result<Self, anyhow::Error> {
+        if call_interval.is_some_and(|interval| interval < Self::MIN_CALL_INTERVAL) {
+            return Err(anyhow::anyhow!(
+                "Call interval must be at least {} ms",
+                Self::MIN_CALL_INTERVAL
+            ));
+        }
+        Ok(Self {
+            balls,
+            call_interval,
+        })
+    }
+
+    /// Calls a random number not called yet and returns when the next timed draw is due.
+    /// `player_id` is empty for timed draws.
+    pub fn draw(
+        &self,
+        called: &mut Vec<SelectedCell>,
+        player_id: &str,
+    ) -> Result<Option<u64>, anyhow::Error> {
+        use rand::seq::SliceRandom;
+        let remaining = (1..=self.balls)
+            .filter(|n| !called.iter().any(|c| c.cell_value == *n))
+            .collect::<Vec<_>>();
+        let number = *remaining
+            .choose(&mut rand::thread_rng())
+            .ok_or_else(|| anyhow::anyhow!("Every number was called"))?;
+        called.push(SelectedCell {
+            cell_value: number,
+            selected_by: player_id.into(),
+            selected_at: now_millis(),
+        });
+        Ok(match self.call_interval {
+            Some(interval) if remaining.len() > 1 => Some(now_millis() + interval),
+            _ => None,
+        })
+    }
+}
+
+/// Numbers a game is played with, from 1 to `size`. Word or emoji Bingo gives each number
+/// a label, which clients show in its place.
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct NumberPool {
+    pub size: Cell,
+    /// Label of each number in order, starting with number 1.
+    pub labels: Option<Vec<String>>,
+}
+
+impl NumberPool {
+    /// Most numbers a custom pool may hold, unless the board needs more.
+    pub const MAX_SIZE: Cell = 1000;
+    /// Longest label, in characters.
+    pub const MAX_LABEL_LENGTH: usize = 32;
+
+    /// Numbers from 1 to `size`, or one for each of the `labels`. Without either, the
+    /// pool has exactly the `board_size²` numbers a board needs.
+    pub fn new(
+        size: Option<Cell>,
+        labels: Option<Vec<String>>,
+        board_size: u16,
+    ) -> Result<Self, anyhow::Error> {
+        let needed = Cell::from(board_size).pow(2);
+        let size = match (size, &labels) {
+            (Some(size), Some(labels)) if size as usize != labels.len() => {
+                return Err(anyhow::anyhow!(
+                    "Expected {} labels, got {}",
+                    size,
+                    labels.len()
+                ))
+            }
+            (_, Some(labels)) => labels.len() as Cell,
+            (Some(size), None) => size,
+            (None, None) => needed,
+        };
+        if size < needed {
+            return Err(anyhow::anyhow!(
+                "A {0}x{0} board needs at least {1} numbers",
+                board_size,
+                needed
+            ));
+        }
+        if size > Self::MAX_SIZE.max(needed) {
+            return Err(anyhow::anyhow!(
+                "The pool can hold at most {} numbers",
+                Self::MAX_SIZE.max(needed)
+            ));
+        }
+        let mut used = HashSet::new();
+        for label in labels.iter().flatten() {
+            if label.trim().is_empty() || label.chars().count() > Self::MAX_LABEL_LENGTH {
+                return Err(anyhow::anyhow!(
+                    "Labels must have 1 to {} characters",
+                    Self::MAX_LABEL_LENGTH
+                ));
+            }
+            if !used.insert(label) {
+                return Err(anyhow::anyhow!("Label {} is used twice", label));
+            }
+        }
+        Ok(Self { size, labels })
+    }
+
+    /// Fails if `number` is not in the pool.
+    pub fn check(&self, number: Cell) -> Result<(), anyhow::Error> {
+        if (1..=self.size).contains(&number) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Number {} is not in the pool", number))
+        }
+    }
+
+    /// Numbers of the pool not called yet, in order.
+    pub fn remaining(&self, called: &[SelectedCell]) -> Vec<Cell> {
+        let called = called.iter().map(|c| c.cell_value).collect::<HashSet<_>>();
+        (1..=self.size).filter(|n| !called.contains(n)).collect()
+    }
+}
+
+impl GameRunning {
+    /// Fails if the number was already called.
+    pub fn check_call(&self, number: Cell) -> Result<(), anyhow::Error> {
+        if self.selected_numbers.iter().any(|c| c.cell_value == number) {
+            Err(anyhow::anyhow!("Invalid move"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject), graphql(complex))]
 pub struct Board {
     pub numbers: Vec<Vec<Cell>>,
+    /// Cells and patterns marked so far in the game.
+    pub marks: Box<BoardMarks>,
 }
 
+#[cfg(feature = "graphql")]
 #[ComplexObject]
 impl Board {
-    pub async fn score<'ctx>(
-        &self,
-        ctx: &Context<'_>,
-        room_id: String,
-    ) -> Result<u32, async_graphql::Error> {
-        let data = ctx.data::<Storage>()?;
-        log::info!("Trying to Get read for room board");
-        let rooms = data.private_rooms.read().await;
-        log::info!("Got read for room board");
-        let room = rooms.get(&room_id).ok_or("Room Not found")?;
+    /// Patterns completed so far.
+    pub async fn score(&self, _ctx: &Context<'_>) -> Result<u32, async_graphql::Error> {
+        Ok(self.marks.score)
+    }
+}
 
-        let game = &room.state.as_game().ok_or("Not game")?.game;
-        let state = &game.as_bingo().ok_or("Not Bingo")?.game_state;
-        match state {
-            GameState::BoardCreation(_) => Ok(0),
-            GameState::GameRunning(state) => Ok(self.get_score(&state.selected_numbers)),
-        }
+/// Board sizes a room may start Bingo with.
+#[derive(Clone, Copy, Debug)]
+pub struct BoardSizeLimits {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl Default for BoardSizeLimits {
+    fn default() -> Self {
+        Self { min: 2, max: 16 }
     }
 }
 
-impl Board {
-    pub fn new(numbers: Vec<Vec<Cell>>, board_size: u16) -> Result<Self, anyhow::Error> {
-        let all_num = numbers.join(&[][..]).into_iter().collect::<HashSet<_>>();
-        if all_num.len() == (board_size * board_size) as usize {
-            if all_num.iter().min().unwrap_or(&0_u32) < &1_u32
-                || all_num
-                    .iter()
-                    .max()
-                    .unwrap_or(&((board_size * board_size + 1) as u32))
-                    > &((board_size * board_size) as u32)
-            {
-                Err(anyhow::anyhow!("Invalid value of board"))
-            } else {
-                Ok(Self { numbers })
-            }
-        } else {
-            Err(anyhow::anyhow!("Invalid Board"))
+impl BoardSizeLimits {
+    /// Largest board size any server accepts, whatever its configured limits.
+    pub const MAX: u16 = 100;
+
+    pub fn check(&self, board_size: u16) -> Result<(), anyhow::Error> {
+        if board_size < self.min || board_size > self.max {
+            return Err(anyhow::anyhow!(
+                "Board size must be between {} and {}",
+                self.min,
+                self.max
+            ));
         }
+        Ok(())
     }
+}
 
-    pub fn get_score(&self, selected_cells: &[SelectedCell]) -> u32 {
-        let mut ndarr = Array2::<u32>::default((self.numbers.len(), self.numbers.len()));
-        let n = self.numbers.len();
-        for (i, mut row) in ndarr.axis_iter_mut(Axis(0)).enumerate() {
-            for (j, col) in row.iter_mut().enumerate() {
-                let val = self.numbers[i][j];
-                if selected_cells.iter().any(|cell| cell.cell_value == val) {
-                    *col = 0;
-                } else {
-                    *col = val;
+/// Cells a board has to mark to score a point.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "graphql", derive(Enum))]
+#[serde(rename_all = "camelCase")]
+pub enum WinPattern {
+    /// Any row, column or diagonal.
+    #[default]
+    Lines,
+    FourCorners,
+    /// Both diagonals.
+    X,
+    /// Any 2x2 square in a corner.
+    PostageStamp,
+    /// Every cell.
+    Blackout,
+    /// The cells set in the rules' `mask`.
+    Custom,
+}
+
+/// How a Bingo board wins, chosen when the game starts.
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct WinRules {
+    pub pattern: WinPattern,
+    /// Cells of a [`WinPattern::Custom`] pattern, row by row.
+    pub mask: Option<Vec<Vec<bool>>>,
+    /// Patterns a board has to complete to win.
+    pub lines_to_win: u32,
+}
+
+impl WinRules {
+    /// Checks the rules against the board size. `lines_to_win` defaults to `board_size`
+    /// for [`WinPattern::Lines`] and to one pattern otherwise.
+    pub fn new(
+        pattern: WinPattern,
+        mask: Option<Vec<Vec<bool>>>,
+        lines_to_win: Option<u32>,
+        board_size: u16,
+    ) -> Result<Self, anyhow::Error> {
+        if board_size > BoardSizeLimits::MAX {
+            return Err(anyhow::anyhow!(
+                "Board size must be at most {}",
+                BoardSizeLimits::MAX
+            ));
+        }
+        let n = board_size as usize;
+        match (pattern, &mask) {
+            (WinPattern::Custom, None) => {
+                return Err(anyhow::anyhow!("The custom pattern needs a mask"))
+            }
+            (WinPattern::Custom, Some(mask)) => {
+                if mask.len() != n || mask.iter().any(|row| row.len() != n) {
+                    return Err(anyhow::anyhow!("Mask must be {0}x{0}", n));
+                }
+                if !mask.iter().flatten().any(|cell| *cell) {
+                    return Err(anyhow::anyhow!("Mask has no cells"));
                 }
             }
+            (_, Some(_)) => return Err(anyhow::anyhow!("Only the custom pattern takes a mask")),
+            (_, None) => {}
         }
-        let mut points = 0;
-        for row in ndarr.rows().into_iter().chain(ndarr.columns()) {
-            if !row.iter().any(|val| *val != 0) {
-                points += 1;
+        let mut rules = Self {
+            pattern,
+            mask,
+            lines_to_win: 1,
+        };
+        let max = rules.patterns(n).len() as u32;
+        rules.lines_to_win = match lines_to_win {
+            Some(lines) if lines < 1 || lines > max => {
+                return Err(anyhow::anyhow!(
+                    "Lines to win must be between 1 and {}",
+                    max
+                ))
             }
+            Some(lines) => lines,
+            None if pattern == WinPattern::Lines => board_size as u32,
+            None => 1,
+        };
+        Ok(rules)
+    }
+
+    /// Rows, columns and diagonals, with a board needing `board_size` of them.
+    pub fn classic(board_size: u16) -> Self {
+        Self {
+            pattern: WinPattern::Lines,
+            mask: None,
+            lines_to_win: board_size as u32,
         }
+    }
 
-        let d1 = (0..n).zip(0..n);
-        let d2 = (0..n).zip((0..n).rev());
-        if !d1.into_iter().any(|i| ndarr.get(i).unwrap_or(&0) != &0) {
-            points += 1;
+    /// Cells of every pattern that scores, as `(row, column)` pairs.
+    pub fn patterns(&self, n: usize) -> Vec<Vec<(usize, usize)>> {
+        if n == 0 {
+            return vec![];
         }
-        if !d2.into_iter().any(|i| ndarr.get(i).unwrap_or(&0) != &0) {
-            points += 1;
+        let last = n - 1;
+        let diagonal = (0..n).map(|i| (i, i)).collect::<Vec<_>>();
+        let anti_diagonal = (0..n).map(|i| (i, last - i)).collect::<Vec<_>>();
+        let mut patterns = match self.pattern {
+            WinPattern::Lines => (0..n)
+                .map(|i| (0..n).map(|j| (i, j)).collect())
+                .chain((0..n).map(|j| (0..n).map(|i| (i, j)).collect()))
+                .chain([diagonal, anti_diagonal])
+                .collect(),
+            WinPattern::FourCorners => vec![vec![(0, 0), (0, last), (last, 0), (last, last)]],
+            WinPattern::X => vec![diagonal.into_iter().chain(anti_diagonal).collect()],
+            WinPattern::PostageStamp => {
+                let far = last.saturating_sub(1);
+                [(0, 0), (0, far), (far, 0), (far, far)]
+                    .iter()
+                    .map(|&(i, j)| vec![(i, j), (i, j + 1), (i + 1, j), (i + 1, j + 1)])
+                    .collect()
+            }
+            WinPattern::Blackout => {
+                vec![(0..n).flat_map(|i| (0..n).map(move |j| (i, j))).collect()]
+            }
+            WinPattern::Custom => vec![self
+                .mask
+                .iter()
+                .flatten()
+                .enumerate()
+                .flat_map(|(i, row)| {
+                    row.iter()
+                        .enumerate()
+                        .filter(|(_, cell)| **cell)
+                        .map(move |(j, _)| (i, j))
+                })
+                .collect()],
+        };
+        // Small boards repeat some patterns, such as the corners of a 1x1 board.
+        for cells in patterns.iter_mut() {
+            cells.retain(|&(i, j)| i < n && j < n);
+            cells.sort_unstable();
+            cells.dedup();
         }
-        points
+        patterns.sort();
+        patterns.dedup();
+        patterns
     }
+}
 
-    pub fn wining_points(&self) -> u32 {
-        self.numbers.len() as u32
+/// Checks that `value` fits a board with `max` cells and was not seen before.
+fn check_number(value: Cell, max: Cell, used: &mut HashSet<Cell>) -> Result<(), anyhow::Error> {
+    if !(1..=max).contains(&value) {
+        return Err(anyhow::anyhow!(
+            "Number {} is not between 1 and {}",
+            value,
+            max
+        ));
+    }
+    if !used.insert(value) {
+        return Err(anyhow::anyhow!("Number {} is used twice", value));
     }
+    Ok(())
+}
 
-    pub fn has_completed(&self, selected_cells: &[SelectedCell]) -> bool {
-        self.get_score(selected_cells) >= self.wining_points()
+impl Board {
+    /// Accepts `board_size` rows of `board_size` numbers, using each of 1 to
+    /// `board_size²` once.
+    pub fn new(numbers: Vec<Vec<Cell>>, board_size: u16) -> Result<Self, anyhow::Error> {
+        Self::with_pool(numbers, board_size, Cell::from(board_size).pow(2))
+    }
+
+    /// Like [`Board::new`], with different numbers taken from 1 to `pool`.
+    pub fn with_pool(
+        numbers: Vec<Vec<Cell>>,
+        board_size: u16,
+        pool: Cell,
+    ) -> Result<Self, anyhow::Error> {
+        if board_size == 0 {
+            return Err(anyhow::anyhow!("Board size must be at least 1"));
+        }
+        let n = board_size as usize;
+        if numbers.len() != n {
+            return Err(anyhow::anyhow!(
+                "Board has {} rows, expected {}",
+                numbers.len(),
+                n
+            ));
+        }
+        if let Some((i, row)) = numbers.iter().enumerate().find(|(_, row)| row.len() != n) {
+            return Err(anyhow::anyhow!(
+                "Row {} has {} numbers, expected {}",
+                i + 1,
+                row.len(),
+                n
+            ));
+        }
+        let mut used = HashSet::new();
+        for &value in numbers.iter().flatten() {
+            check_number(value, pool, &mut used)?;
+        }
+        Ok(Self {
+            numbers,
+            marks: Default::default(),
+        })
     }
 
-    pub fn max_points(&self) -> u32 {
-        (self.numbers.len() * 2 + 2) as u32
+    /// Completes a partly filled board with random unused numbers from 1 to `pool`.
+    /// `0` marks an empty cell, and missing rows or trailing cells are empty too.
+    pub fn fill(
+        partial: Vec<Vec<Cell>>,
+        board_size: u16,
+        pool: Cell,
+    ) -> Result<Self, anyhow::Error> {
+        use rand::seq::SliceRandom;
+        let n = board_size as usize;
+        if partial.len() > n || partial.iter().any(|row| row.len() > n) {
+            return Err(anyhow::anyhow!("Board is larger than {0}x{0}", n));
+        }
+        if u64::from(pool) < (n as u64).pow(2) {
+            return Err(anyhow::anyhow!(
+                "A {0}x{0} board needs more than {1} numbers",
+                n,
+                pool
+            ));
+        }
+        let mut used = HashSet::new();
+        for &value in partial.iter().flatten().filter(|v| **v != 0) {
+            check_number(value, pool, &mut used)?;
+        }
+        let mut missing = (1..=pool).filter(|v| !used.contains(v)).collect::<Vec<_>>();
+        missing.shuffle(&mut rand::thread_rng());
+        let numbers = (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| match partial.get(i).and_then(|row| row.get(j)) {
+                        Some(&value) if value != 0 => value,
+                        _ => missing.pop().unwrap_or_default(),
+                    })
+                    .collect()
+            })
+            .collect();
+        Self::with_pool(numbers, board_size, pool)
+    }
+
+    /// A board of random numbers from 1 to `pool`.
+    pub fn random(board_size: u16, pool: Cell) -> Result<Self, anyhow::Error> {
+        Self::fill(vec![], board_size, pool)
+    }
+
+    /// Patterns of `rules` completed once the `selected_cells` are marked.
+    pub fn get_score(&self, selected_cells: &[SelectedCell], rules: &WinRules) -> u32 {
+        let called = selected_cells
+            .iter()
+            .map(|cell| cell.cell_value)
+            .collect::<HashSet<_>>();
+        rules
+            .patterns(self.numbers.len())
+            .iter()
+            .filter(|cells| {
+                cells.iter().all(|&(i, j)| {
+                    self.numbers
+                        .get(i)
+                        .and_then(|row| row.get(j))
+                        .is_some_and(|value| called.contains(value))
+                })
+            })
+            .count() as u32
+    }
+
+    pub fn has_completed(&self, selected_cells: &[SelectedCell], rules: &WinRules) -> bool {
+        self.get_score(selected_cells, rules) >= rules.lines_to_win
+    }
+
+    pub fn max_points(&self, rules: &WinRules) -> u32 {
+        rules.patterns(self.numbers.len()).len() as u32
+    }
+
+    /// Row and column of `number`, if the board has it.
+    pub fn position(&self, number: Cell) -> Option<(usize, usize)> {
+        self.numbers.iter().enumerate().find_map(|(i, row)| {
+            row.iter()
+                .position(|value| *value == number)
+                .map(|j| (i, j))
+        })
     }
 }
 
-#[derive(Clone, Serialize, SimpleObject)]
-pub struct BingoPlayerData {
-    board: Option<Board>,
+/// The patterns of the win rules laid over a board, to find those a cell counts for.
+#[derive(Clone, Debug, Default)]
+pub struct PatternIndex {
+    board_size: usize,
+    /// Patterns each cell is part of, row by row.
+    cell_patterns: Vec<Vec<usize>>,
+    lines: Vec<Line>,
 }
 
-pub struct BingoInputs {
-    pub room_id: String,
-    pub player_id: String,
+impl PatternIndex {
+    pub fn new(rules: &WinRules, board_size: u16) -> Self {
+        let n = board_size as usize;
+        let patterns = rules.patterns(n);
+        let mut cell_patterns = vec![vec![]; n * n];
+        for (pattern, cells) in patterns.iter().enumerate() {
+            for &(i, j) in cells {
+                cell_patterns[i * n + j].push(pattern);
+            }
+        }
+        Self {
+            board_size: n,
+            cell_patterns,
+            lines: patterns
+                .iter()
+                .enumerate()
+                .map(|(pattern, cells)| Line::new(pattern, cells, n))
+                .collect(),
+        }
+    }
 }
 
-#[Object]
-impl BingoInputs {
-    pub async fn start_game<'ctx>(
-        &self,
-        ctx: &Context<'_>,
-        board_size: u16,
-    ) -> Result<bool, async_graphql::Error> {
-        let room = {
-            let data = ctx.data::<Storage>()?;
-
-            let mut rooms = data.private_rooms.write().await;
-            let room = rooms
-                .get_mut(&self.room_id)
-                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
-            room.handle_player_message(
-                &self.player_id,
-                PlayerEvents::StartGame(StartMessages::BingoStart(BingoStart { board_size })),
-            )
-            .await?;
-            room.clone()
-        };
+/// Whether a scoring pattern is a row, column or diagonal.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "graphql", derive(Enum))]
+#[serde(rename_all = "camelCase")]
+pub enum LineKind {
+    Row,
+    Column,
+    /// From the top left to the bottom right corner.
+    Diagonal,
+    /// From the top right to the bottom left corner.
+    AntiDiagonal,
+    /// Any other pattern of the win rules.
+    Pattern,
+}
 
-        room.clone()
-            .state
-            .broadcast(ServerResponse::GameMessage(GameMessage {
-                event: GameEvents::GameStarted(GameStarted {
-                    game: room.state.as_game().ok_or("Not game")?.game.clone(),
-                }),
-                room: room.clone(),
-            }))
-            .await;
-        Ok(true)
+/// Row and column of a board cell, counting from 0.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct CellPosition {
+    pub row: u32,
+    pub column: u32,
+}
+
+/// A pattern of the win rules laid over the board.
+#[derive(Serialize, Clone, Debug)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct Line {
+    pub kind: LineKind,
+    /// Row or column number for rows and columns, and the pattern number in
+    /// [`WinRules::patterns`] otherwise.
+    pub index: u32,
+    pub cells: Vec<CellPosition>,
+}
+
+impl Line {
+    fn new(pattern: usize, cells: &[(usize, usize)], n: usize) -> Self {
+        let all = |f: &dyn Fn(usize, usize) -> bool| {
+            cells.len() == n && cells.iter().all(|&(i, j)| f(i, j))
+        };
+        let (first_row, first_column) = cells.first().copied().unwrap_or_default();
+        let (kind, index) = if all(&|i, _| i == first_row) {
+            (LineKind::Row, first_row)
+        } else if all(&|_, j| j == first_column) {
+            (LineKind::Column, first_column)
+        } else if all(&|i, j| i == j) {
+            (LineKind::Diagonal, 0)
+        } else if all(&|i, j| i + j + 1 == n) {
+            (LineKind::AntiDiagonal, 0)
+        } else {
+            (LineKind::Pattern, pattern)
+        };
+        Self {
+            kind,
+            index: index as u32,
+            cells: cells
+                .iter()
+                .map(|&(i, j)| CellPosition {
+                    row: i as u32,
+                    column: j as u32,
+                })
+                .collect(),
+        }
     }
+}
 
-    pub async fn ready_board<'ctx>(
-        &self,
-        ctx: &Context<'_>,
-        board: Vec<Vec<u32>>,
-    ) -> Result<bool, async_graphql::Error> {
-        let data = ctx.data::<Storage>()?;
-        let room = {
-            let mut rooms = data.private_rooms.write().await;
-
-            let room = rooms
-                .get_mut(&self.room_id)
-                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
-            let board_size = room
-                .state
-                .as_game()
-                .ok_or_else(|| async_graphql::Error::from("Game not running"))?
-                .game
-                .as_bingo()
-                .map(|b| b.board_size)
-                .ok_or("Cant find board size")?;
-
-            room.handle_player_message(
-                &self.player_id,
-                PlayerEvents::GameMessage(PlayerMessages::BingoMessages(
-                    BingoPlayerMessages::ReadyBoard(Board::new(board, board_size)?),
-                )),
-            )
-            .await?;
-            room.clone()
+#[derive(Serialize, Clone, Debug)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct CompletedLine {
+    pub line: Line,
+    /// Index of the call that completed the line, in the game's called numbers.
+    pub call: u32,
+    /// Number whose call completed the line.
+    pub number: Cell,
+}
+
+/// What a board has marked so far. It is updated once for every called number, so scores
+/// never have to be worked out from the called numbers again.
+#[derive(Serialize, Clone, Debug, Default)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct BoardMarks {
+    /// Marked cells, row by row.
+    pub marked: Vec<Vec<bool>>,
+    /// Patterns completed so far, in the order they were completed.
+    pub completed: Vec<CompletedLine>,
+    /// Patterns completed after each call, for replaying the game move by move.
+    pub progression: Vec<u32>,
+    /// Patterns completed.
+    pub score: u32,
+    /// Marked cells of each pattern, in the order of [`WinRules::patterns`].
+    #[serde(skip)]
+    #[cfg_attr(feature = "graphql", graphql(skip))]
+    pub pattern_marks: Vec<u32>,
+    /// Index of the call that brought the score up to its current value, counting no
+    /// further than the lines to win.
+    #[serde(skip)]
+    #[cfg_attr(feature = "graphql", graphql(skip))]
+    pub scored_at: Option<usize>,
+}
+
+impl Board {
+    /// Clears the marks, ready for the first call.
+    pub fn reset_marks(&mut self, index: &PatternIndex) {
+        let n = self.numbers.len();
+        *self.marks = BoardMarks {
+            marked: vec![vec![false; n]; n],
+            pattern_marks: vec![0; index.lines.len()],
+            ..Default::default()
         };
+    }
+
+    /// Marks `number`, the next called number, if the board has it.
+    pub fn mark(&mut self, index: &PatternIndex, number: Cell, lines_to_win: u32) {
+        let call = self.marks.progression.len();
+        if let Some((i, j)) = self.position(number) {
+            let marks = &mut self.marks;
+            if !marks.marked[i][j] {
+                marks.marked[i][j] = true;
+                for &pattern in &index.cell_patterns[i * index.board_size + j] {
+                    let line = &index.lines[pattern];
+                    marks.pattern_marks[pattern] += 1;
+                    if marks.pattern_marks[pattern] as usize == line.cells.len() {
+                        marks.score += 1;
+                        if marks.score <= lines_to_win {
+                            marks.scored_at = Some(call);
+                        }
+                        marks.completed.push(CompletedLine {
+                            line: line.clone(),
+                            call: call as u32,
+                            number,
+                        });
+                    }
+                }
+            }
+        }
+        self.marks.progression.push(self.marks.score);
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct BingoPlayerData {
+    /// The player's cards, empty until they are ready.
+    pub boards: Vec<Board>,
+}
 
-        room.clone()
-            .state
-            .broadcast(ServerResponse::GameMessage(GameMessage {
-                event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
-                room: room.clone(),
-            }))
-            .await;
-        Ok(true)
+impl BingoPlayerData {
+    /// Most patterns any of the cards completed.
+    pub fn score(&self) -> u32 {
+        self.boards.iter().map(|b| b.marks.score).max().unwrap_or(0)
     }
 
-    pub async fn player_move<'ctx>(
-        &self,
-        ctx: &Context<'_>,
-        number: u32,
-    ) -> Result<bool, async_graphql::Error> {
-        let data = ctx.data::<Storage>()?;
-        let room = {
-            let mut rooms = data.private_rooms.write().await;
-
-            let room = rooms
-                .get_mut(&self.room_id)
-                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
-            room.handle_player_message(
-                &self.player_id,
-                PlayerEvents::GameMessage(PlayerMessages::BingoMessages(
-                    BingoPlayerMessages::Move(number),
-                )),
+    /// The card that reached the most patterns, up to `lines_to_win`, first.
+    pub fn best_board(&self, lines_to_win: u32) -> Option<&Board> {
+        self.boards.iter().min_by_key(|b| {
+            (
+                Reverse(b.marks.score.min(lines_to_win)),
+                b.marks.scored_at.unwrap_or(0),
             )
-            .await?;
-            room.clone()
-        };
+        })
+    }
 
-        room.clone()
-            .state
-            .broadcast(ServerResponse::GameMessage(GameMessage {
-                event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
-                room: room.clone(),
-            }))
-            .await;
-        Ok(true)
+    /// Score of the best card up to `lines_to_win`, and the index of the call that
+    /// reached it.
+    pub fn standing(&self, lines_to_win: u32) -> (u32, usize) {
+        self.best_board(lines_to_win).map_or((0, 0), |b| {
+            (
+                b.marks.score.min(lines_to_win),
+                b.marks.scored_at.unwrap_or(0),
+            )
+        })
     }
 }
@@ -1,38 +1,46 @@
 use std::collections::HashMap;
 
-use async_graphql::{Context, Enum, Object, SimpleObject};
+#[cfg(feature = "graphql")]
+use async_graphql::{Enum, Object, SimpleObject};
 use rand::{prelude::IteratorRandom, SeedableRng};
 use serde::Serialize;
 
-use crate::{
-    data::{GameMessage, Rank, ServerResponse, Storage},
-    logic::{GameEvents, GameStarted, PlayerEvents, RoomUpdate},
-};
+use crate::utils::now_millis;
 
-use super::{GameTrait, PlayerGameData, PlayerMessages, StartMessages};
+use super::{GamePlayer, GameTrait, Player, PlayerGameData, Rank};
 
 #[derive(Clone, Serialize)]
 
 pub struct Bluff {
     turn_start: String,
     turn: String,
-    centered_card: Vec<(String,Vec<Card>)>,
+    /// Cards dealt this round with the dealer and when they were dealt.
+    centered_card: Vec<(String, Vec<Card>, u64)>,
     deck_card: Vec<Card>,
     claimed: Option<Card>,
 }
 
-#[derive(Clone, Serialize, PartialEq, Eq, SimpleObject, Debug)]
+#[derive(Clone, Serialize, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
 pub struct Card {
     number: CardNum,
     color: CardColor,
 }
 
-#[derive(Clone, Serialize, SimpleObject)]
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
 pub struct BluffPlayerData {
     cards: Vec<Card>,
     end_turn_raised: bool,
 }
 
+impl BluffPlayerData {
+    /// Cards left in the player's hand.
+    pub fn hand(&self) -> &[Card] {
+        &self.cards
+    }
+}
+
 impl From<u8> for Card {
     fn from(num: u8) -> Self {
         if num > 51 {
@@ -68,7 +76,8 @@ impl From<u8> for Card {
     }
 }
 
-#[derive(Clone, Serialize, Copy, PartialEq, Eq, Enum, Debug)]
+#[derive(Clone, Serialize, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "graphql", derive(Enum))]
 pub enum CardColor {
     Spade,
     Heart,
@@ -76,7 +85,8 @@ pub enum CardColor {
     Diamond,
 }
 
-#[derive(Clone, Serialize, Copy, PartialEq, Eq, Enum, Debug)]
+#[derive(Clone, Serialize, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "graphql", derive(Enum))]
 pub enum CardNum {
     Ace,
     Two,
@@ -101,7 +111,7 @@ pub enum BluffPlayerMessages {
 }
 
 pub struct StartBluff {
-    seed: u64,
+    pub seed: u64,
 }
 
 impl GameTrait for Bluff {
@@ -111,7 +121,9 @@ impl GameTrait for Bluff {
 
     type PlayerGameData = BluffPlayerData;
 
-    type InputHandler = BluffInputs;
+    fn player_range() -> std::ops::RangeInclusive<usize> {
+        2..=8
+    }
 
     fn is_game_running(&self) -> bool {
         true
@@ -121,7 +133,7 @@ impl GameTrait for Bluff {
         self.turn == player_id
     }
 
-    fn get_rankings(&self, players: &[crate::logic::GamePlayer]) -> Vec<crate::data::Rank> {
+    fn get_rankings(&self, players: &[GamePlayer]) -> Vec<Rank> {
         let mut scored = players
             .iter()
             .map(|p| {
@@ -131,7 +143,7 @@ impl GameTrait for Bluff {
                 )
             })
             .collect::<Vec<_>>();
-        scored.sort_by(|p1, p2| p1.0.cmp(&p2.0));
+        scored.sort_by_key(|p1| p1.0);
         let mut ranks = vec![];
         let mut last_rank = 0;
         let mut last_score = usize::MAX;
@@ -148,14 +160,13 @@ impl GameTrait for Bluff {
         ranks
     }
 
-    fn get_next_turn_player(&self, players: &[crate::logic::GamePlayer]) -> Option<String> {
+    fn get_next_turn_player(&self, players: &[GamePlayer]) -> Option<String> {
         let mut cycle_iter = players.iter().cycle();
         let current_player_position = players.iter().position(|p| p.player.id == self.turn);
         if let Some(position) = current_player_position {
             cycle_iter.nth(position);
             for player in cycle_iter {
-                if player.send_channel.is_some()
-                    && !player.data.as_bluff_player_data().unwrap().cards.is_empty()
+                if player.connected && !player.data.as_bluff_player_data().unwrap().cards.is_empty()
                 {
                     return Some(player.player.id.clone());
                 }
@@ -173,7 +184,7 @@ impl GameTrait for Bluff {
     fn handle_player_message(
         &mut self,
         player_id: &str,
-        players: &mut [crate::logic::GamePlayer],
+        players: &mut [GamePlayer],
         message: Self::PlayerMessage,
     ) -> Result<(), anyhow::Error> {
         match message {
@@ -209,23 +220,13 @@ impl GameTrait for Bluff {
                 if self.turn != player_id {
                     return Err(anyhow::anyhow!("Not your Turn"));
                 }
-                if let Some(claimed_previously) = &self.claimed {
-                    if claimed_previously != &claim {
-                        return Err(anyhow::anyhow!(
-                            "Cant claim another card in middle of round"
-                        ));
-                    }
-                }
+                Self::check_claim(self.claimed.as_ref(), &claim)?;
                 let p = players.iter_mut().find(|p| p.player.id == player_id);
                 if let Some(p) = p {
                     if let PlayerGameData::BluffPlayerData(data) = &mut p.data {
-                        data.cards = data
-                            .cards
-                            .iter()
-                            .cloned()
-                            .filter(|f| !cards.contains(f))
-                            .collect();
-                        self.centered_card.push((p.player.id.clone(),cards));
+                        data.cards.retain(|f| !cards.contains(f));
+                        self.centered_card
+                            .push((p.player.id.clone(), cards, now_millis()));
                         self.claimed = Some(claim);
                         if let Some(player) = self.get_next_turn_player(players) {
                             self.change_turn(&player);
@@ -250,10 +251,10 @@ impl GameTrait for Bluff {
                 let mut to_transfer = None;
                 if let Some(last_cards) = self.centered_card.last() {
                     if let Some(claimed) = &self.claimed {
-                        if last_cards.1.iter().all(|card| card.number == claimed.number) {
-                            to_transfer = Some(player_id.to_string());
-                        } else {
+                        if Self::is_bluff(&last_cards.1, claimed) {
                             to_transfer = Some(last_cards.0.clone());
+                        } else {
+                            to_transfer = Some(player_id.to_string());
                         }
                     }
                 }
@@ -275,8 +276,8 @@ impl GameTrait for Bluff {
         }
     }
 
-    fn is_game_end(&self, players: &[crate::logic::GamePlayer]) -> bool {
-        players.iter().filter(|p| p.send_channel.is_some()).count() <= 1
+    fn is_game_end(&self, players: &[GamePlayer]) -> bool {
+        players.iter().filter(|p| p.connected).count() <= 1
             || players
                 .iter()
                 .filter(|p| !p.data.as_bluff_player_data().unwrap().cards.is_empty())
@@ -286,7 +287,7 @@ impl GameTrait for Bluff {
 
     fn create_player_data(
         data: &Self::StartMessage,
-        players: &[crate::data::Player],
+        players: &[Player],
         player_id: &str,
     ) -> Self::PlayerGameData {
         let mut choosen: HashMap<String, Vec<i32>> = HashMap::new();
@@ -299,10 +300,10 @@ impl GameTrait for Bluff {
                         acc.append(&mut i.1.clone());
                         acc
                     })
-                    .contains(&f)
+                    .contains(f)
             });
 
-            let c = cards.choose_multiple(&mut rand, 52 / players.len());
+            let c = cards.choose_multiple(&mut rand, 52 / players.len().max(1));
             choosen.insert(player.id.clone(), c);
         }
 
@@ -318,17 +319,9 @@ impl GameTrait for Bluff {
         }
     }
 
-    fn input_handler(room_id: String, player_id: String) -> Self::InputHandler {
-        Self::InputHandler { room_id, player_id }
-    }
-
-    fn start_game(
-        _data: Self::StartMessage,
-        players: &[crate::logic::GamePlayer],
-        player_id: &str,
-    ) -> Self {
+    fn start_game(_data: Self::StartMessage, players: &[GamePlayer], player_id: &str) -> Self {
         let cards = (0..52)
-            .map(|i| Card::from(i))
+            .map(Card::from)
             .filter(|f| {
                 !players
                     .iter()
@@ -336,7 +329,7 @@ impl GameTrait for Bluff {
                         acc.append(&mut p.data.as_bluff_player_data().unwrap().cards.clone());
                         acc
                     })
-                    .contains(&f)
+                    .contains(f)
             })
             .collect();
         Self {
@@ -350,14 +343,29 @@ impl GameTrait for Bluff {
 }
 
 impl Bluff {
-    fn get_next_round_player(&self, players: &[crate::logic::GamePlayer]) -> Option<String> {
+    /// Fails if the claim differs from the one already made this round.
+    pub fn check_claim(claimed: Option<&Card>, claim: &Card) -> Result<(), anyhow::Error> {
+        match claimed {
+            Some(claimed) if claimed != claim => Err(anyhow::anyhow!(
+                "Cant claim another card in middle of round"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns `true` if any of the dealt cards differs in number from the claim. The
+    /// dealer then takes the pile when flipped, otherwise the player who flipped does.
+    pub fn is_bluff(cards: &[Card], claimed: &Card) -> bool {
+        cards.iter().any(|card| card.number != claimed.number)
+    }
+
+    fn get_next_round_player(&self, players: &[GamePlayer]) -> Option<String> {
         let mut cycle_iter = players.iter().cycle();
         let current_player_position = players.iter().position(|p| p.player.id == self.turn_start);
         if let Some(position) = current_player_position {
             cycle_iter.nth(position);
             for player in cycle_iter {
-                if player.send_channel.is_some()
-                    && !player.data.as_bluff_player_data().unwrap().cards.is_empty()
+                if player.connected && !player.data.as_bluff_player_data().unwrap().cards.is_empty()
                 {
                     return Some(player.player.id.clone());
                 }
@@ -369,168 +377,7 @@ impl Bluff {
     }
 }
 
-pub struct BluffInputs {
-    pub room_id: String,
-    pub player_id: String,
-}
-
-#[Object]
-impl BluffInputs {
-    pub async fn start_game<'ctx>(
-        &self,
-        ctx: &Context<'_>,
-        seed: u64,
-    ) -> Result<bool, async_graphql::Error> {
-        let room = {
-            let data = ctx.data::<Storage>()?;
-
-            let mut rooms = data.private_rooms.write().await;
-            let room = rooms
-                .get_mut(&self.room_id)
-                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
-            room.handle_player_message(
-                &self.player_id,
-                PlayerEvents::StartGame(StartMessages::BluffStart(StartBluff { seed })),
-            )
-            .await?;
-            room.clone()
-        };
-
-        room.clone()
-            .state
-            .broadcast(ServerResponse::GameMessage(GameMessage {
-                event: GameEvents::GameStarted(GameStarted {
-                    game: room.state.as_game().ok_or("Not game")?.game.clone(),
-                }),
-                room: room.clone(),
-            }))
-            .await;
-        Ok(true)
-    }
-
-    pub async fn pass<'ctx>(&self, ctx: &Context<'_>) -> Result<bool, async_graphql::Error> {
-        let data = ctx.data::<Storage>()?;
-        let room = {
-            let mut rooms = data.private_rooms.write().await;
-
-            let room = rooms
-                .get_mut(&self.room_id)
-                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
-            room.handle_player_message(
-                &self.player_id,
-                PlayerEvents::GameMessage(PlayerMessages::BluffPlayerMessages(
-                    BluffPlayerMessages::Pass,
-                )),
-            )
-            .await?;
-            room.clone()
-        };
-
-        room.clone()
-            .state
-            .broadcast(ServerResponse::GameMessage(GameMessage {
-                event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
-                room: room.clone(),
-            }))
-            .await;
-        Ok(true)
-    }
-
-    pub async fn flip<'ctx>(&self, ctx: &Context<'_>) -> Result<bool, async_graphql::Error> {
-        let data = ctx.data::<Storage>()?;
-        let room = {
-            let mut rooms = data.private_rooms.write().await;
-
-            let room = rooms
-                .get_mut(&self.room_id)
-                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
-            room.handle_player_message(
-                &self.player_id,
-                PlayerEvents::GameMessage(PlayerMessages::BluffPlayerMessages(
-                    BluffPlayerMessages::Flip,
-                )),
-            )
-            .await?;
-            room.clone()
-        };
-
-        room.clone()
-            .state
-            .broadcast(ServerResponse::GameMessage(GameMessage {
-                event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
-                room: room.clone(),
-            }))
-            .await;
-        Ok(true)
-    }
-
-    pub async fn vote_round_end<'ctx>(
-        &self,
-        ctx: &Context<'_>,
-    ) -> Result<bool, async_graphql::Error> {
-        let data = ctx.data::<Storage>()?;
-        let room = {
-            let mut rooms = data.private_rooms.write().await;
-
-            let room = rooms
-                .get_mut(&self.room_id)
-                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
-            room.handle_player_message(
-                &self.player_id,
-                PlayerEvents::GameMessage(PlayerMessages::BluffPlayerMessages(
-                    BluffPlayerMessages::RaiseEndRound,
-                )),
-            )
-            .await?;
-            room.clone()
-        };
-
-        room.clone()
-            .state
-            .broadcast(ServerResponse::GameMessage(GameMessage {
-                event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
-                room: room.clone(),
-            }))
-            .await;
-        Ok(true)
-    }
-    pub async fn deal<'ctx>(
-        &self,
-        ctx: &Context<'_>,
-        cards: Vec<u8>,
-        claim: u8,
-    ) -> Result<bool, async_graphql::Error> {
-        let data = ctx.data::<Storage>()?;
-        let room = {
-            let mut rooms = data.private_rooms.write().await;
-
-            let room = rooms
-                .get_mut(&self.room_id)
-                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
-            room.handle_player_message(
-                &self.player_id,
-                PlayerEvents::GameMessage(PlayerMessages::BluffPlayerMessages(
-                    BluffPlayerMessages::Deal(
-                        cards.into_iter().map(|c| Card::from(c)).collect(),
-                        Card::from(claim),
-                    ),
-                )),
-            )
-            .await?;
-            room.clone()
-        };
-
-        room.clone()
-            .state
-            .broadcast(ServerResponse::GameMessage(GameMessage {
-                event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
-                room: room.clone(),
-            }))
-            .await;
-        Ok(true)
-    }
-}
-
+#[cfg(feature = "graphql")]
 #[Object]
 impl Bluff {
     pub async fn deck(&self) -> Vec<Card> {
@@ -538,7 +385,12 @@ impl Bluff {
     }
 
     pub async fn centered_card(&self) -> Vec<Vec<Card>> {
-        self.centered_card.iter().map(|f|f.1.clone()).collect()
+        self.centered_card.iter().map(|f| f.1.clone()).collect()
+    }
+
+    /// When each pile in `centered_card` was dealt, in milliseconds since the epoch.
+    pub async fn dealt_at(&self) -> Vec<u64> {
+        self.centered_card.iter().map(|f| f.2).collect()
     }
 
     pub async fn turn(&self) -> String {
@@ -1,19 +1,18 @@
 use std::cmp::Ordering;
 
-use async_graphql::{ComplexObject, Context, Object, Result, SimpleObject, Union};
+#[cfg(feature = "graphql")]
+use async_graphql::{Object, Result, SimpleObject, Union};
 
 use colors_transform::Color;
 use ndarray::Array2;
 use serde::Serialize;
 
-use crate::{
-    data::{GameMessage, Player, Rank, ServerResponse, Storage},
-    logic::{GameEvents, GamePlayer, GameStarted, PlayerEvents, RoomUpdate},
-};
+use crate::utils::now_millis;
 
-use super::{GameTrait, PlayerMessages, StartMessages};
+use super::{GamePlayer, GameTrait, Player, Rank, TeamRank};
 
-#[derive(Clone, Serialize, Union)]
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "graphql", derive(Union))]
 pub enum EdgeType {
     Occupied(Occupied),
     Unoccupied(Unoccupied),
@@ -47,14 +46,18 @@ impl EdgeType {
     }
 }
 
-#[derive(Clone, Serialize, SimpleObject)]
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
 pub struct Occupied {
     pub mov_no: u32,
     pub occupied_by: String,
     pub id: u32,
+    /// Milliseconds since the epoch when the edge was drawn.
+    pub occupied_at: u64,
 }
 
-#[derive(Clone, Serialize, SimpleObject)]
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
 pub struct Unoccupied {
     pub id: u32,
 }
@@ -78,22 +81,23 @@ pub struct BoxesStart {
     pub board_height: u32,
 }
 
-#[derive(Clone, Serialize, SimpleObject)]
-#[graphql(complex)]
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+#[cfg_attr(feature = "server", graphql(complex))]
 pub struct BoxesPlayerData {
     pub color: String,
-}
 
-pub struct BoxesInputs {
-    pub room_id: String,
+    #[cfg_attr(feature = "graphql", graphql(skip))]
     pub player_id: String,
 }
 
-#[derive(Default, Clone, Serialize, SimpleObject)]
+#[derive(Default, Clone, Serialize)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
 pub struct Cell {
     pub occupied_by: Option<String>,
 }
 
+#[cfg(feature = "graphql")]
 #[Object]
 impl Boxes {
     pub async fn vertical_edges(&self) -> Result<&[EdgeType], async_graphql::Error> {
@@ -129,6 +133,85 @@ impl Boxes {
 }
 
 impl Boxes {
+    /// Creates an empty board. Edge ids count up from 1 through `horizontal_edges`, then
+    /// `vertical_edges`.
+    pub fn new(board_width: u32, board_height: u32, turn: String) -> Self {
+        let mut id = 0;
+        let horizontal_edges = Array2::<EdgeType>::from_shape_fn(
+            (board_height as usize, (board_width + 1) as usize),
+            |_| {
+                id += 1;
+                EdgeType::Unoccupied(Unoccupied { id })
+            },
+        );
+        let vertical_edges = Array2::<EdgeType>::from_shape_fn(
+            ((board_height + 1) as usize, board_width as usize),
+            |_| {
+                id += 1;
+                EdgeType::Unoccupied(Unoccupied { id })
+            },
+        );
+        Self {
+            horizontal_edges,
+            vertical_edges,
+            turn,
+        }
+    }
+
+    /// Fails unless the edge exists and nobody drew it yet.
+    pub fn check_edge(&self, edge_id: u32) -> Result<(), anyhow::Error> {
+        match self
+            .horizontal_edges
+            .iter()
+            .chain(self.vertical_edges.iter())
+            .find(|e| match e {
+                EdgeType::Occupied(o) => o.id == edge_id,
+                EdgeType::Unoccupied(u) => u.id == edge_id,
+            }) {
+            None => Err(anyhow::anyhow!("Cant find edge")),
+            Some(EdgeType::Occupied(_)) => Err(anyhow::anyhow!("Edge already occupied")),
+            Some(EdgeType::Unoccupied(_)) => Ok(()),
+        }
+    }
+
+    /// Draws the edge for the player without checking the turn. Returns `true` if the
+    /// edge completed a box, in which case the player moves again.
+    pub fn occupy(&mut self, player_id: &str, edge_id: u32) -> Result<bool, anyhow::Error> {
+        self.check_edge(edge_id)?;
+        let previous_cell_count = self
+            .get_cells()
+            .iter()
+            .filter(|c| c.occupied_by.is_some())
+            .count();
+        let mov_no = self
+            .horizontal_edges
+            .iter()
+            .chain(self.vertical_edges.iter())
+            .filter_map(|e| e.as_occupied().map(|o| o.mov_no))
+            .max()
+            .unwrap_or_default()
+            + 1;
+        if let Some(edge) = self
+            .horizontal_edges
+            .iter_mut()
+            .chain(self.vertical_edges.iter_mut())
+            .find(|e| e.as_unoccupied().map(|u| u.id) == Some(edge_id))
+        {
+            *edge = EdgeType::Occupied(Occupied {
+                mov_no,
+                occupied_by: player_id.into(),
+                id: edge_id,
+                occupied_at: now_millis(),
+            });
+        }
+        let new_cell_count = self
+            .get_cells()
+            .iter()
+            .filter(|c| c.occupied_by.is_some())
+            .count();
+        Ok(new_cell_count > previous_cell_count)
+    }
+
     pub fn get_cells(&self) -> Array2<Cell> {
         let height = self.horizontal_edges.rows().into_iter().len();
         let width = self.vertical_edges.columns().into_iter().len();
@@ -202,6 +285,25 @@ impl Boxes {
             })
             .count() as u32
     }
+
+    /// Counts the boxes completed by the player together with their teammates.
+    pub fn get_team_score(&self, players: &[GamePlayer], player: &Player) -> u32 {
+        let teammates = players
+            .iter()
+            .filter(|p| p.player.is_teammate(player))
+            .map(|p| p.player.id.as_str())
+            .collect::<Vec<_>>();
+        self.get_cells()
+            .iter()
+            .filter(|c| {
+                if let Some(occ_by) = &c.occupied_by {
+                    teammates.contains(&occ_by.as_str())
+                } else {
+                    false
+                }
+            })
+            .count() as u32
+    }
 }
 
 impl GameTrait for Boxes {
@@ -211,7 +313,9 @@ impl GameTrait for Boxes {
 
     type PlayerGameData = BoxesPlayerData;
 
-    type InputHandler = BoxesInputs;
+    fn player_range() -> std::ops::RangeInclusive<usize> {
+        2..=8
+    }
 
     fn is_game_running(&self) -> bool {
         true
@@ -243,9 +347,36 @@ impl GameTrait for Boxes {
         ranks
     }
 
+    fn get_team_rankings(&self, players: &[GamePlayer]) -> Vec<TeamRank> {
+        let mut scored = Player::sides(players.iter().map(|p| &p.player))
+            .into_iter()
+            .map(|side| (self.get_team_score(players, side), side))
+            .collect::<Vec<_>>();
+        scored.sort_by(|p1, p2| p1.0.cmp(&p2.0).reverse());
+        let mut ranks = vec![];
+        let mut last_rank = 0;
+        let mut last_score = u32::MAX;
+        for p in scored.iter() {
+            if last_score > p.0 {
+                last_rank += 1;
+                last_score = p.0;
+            }
+            ranks.push(TeamRank {
+                rank: last_rank,
+                team: p.1.team,
+                players: players
+                    .iter()
+                    .filter(|pl| pl.player.is_teammate(p.1))
+                    .map(|pl| pl.player.clone())
+                    .collect(),
+            })
+        }
+        ranks
+    }
+
     fn get_next_turn_player(&self, players: &[GamePlayer]) -> Option<String> {
         if self.get_cells().iter().all(|p| p.occupied_by.is_some())
-            || players.iter().all(|p| p.send_channel.is_none())
+            || players.iter().all(|p| !p.connected)
         {
             None
         } else {
@@ -254,7 +385,7 @@ impl GameTrait for Boxes {
             if let Some(position) = current_player_position {
                 cycle_iter.nth(position);
                 for player in cycle_iter {
-                    if player.send_channel.is_some() {
+                    if player.connected {
                         return Some(player.player.id.clone());
                     }
                 }
@@ -269,6 +400,27 @@ impl GameTrait for Boxes {
         self.turn = player_id.into();
     }
 
+    fn points_scored(&self, player_id: &str) -> Option<u32> {
+        Some(self.get_score(player_id))
+    }
+
+    fn undo_last_move(&mut self, _players: &mut [GamePlayer]) -> Result<String, anyhow::Error> {
+        let edge = self
+            .horizontal_edges
+            .iter_mut()
+            .chain(self.vertical_edges.iter_mut())
+            .filter(|e| e.is_occupied())
+            .max_by_key(|e| e.as_occupied().map(|o| o.mov_no))
+            .ok_or_else(|| anyhow::anyhow!("No move to undo"))?;
+        let occupied = edge
+            .as_occupied()
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No move to undo"))?;
+        *edge = EdgeType::Unoccupied(Unoccupied { id: occupied.id });
+        self.turn = occupied.occupied_by.clone();
+        Ok(occupied.occupied_by)
+    }
+
     fn handle_player_message(
         &mut self,
         player_id: &str,
@@ -280,61 +432,7 @@ impl GameTrait for Boxes {
                 if self.turn != player_id {
                     return Err(anyhow::anyhow!("Not your Turn"));
                 }
-                let previous_cell_count = self
-                    .get_cells()
-                    .iter()
-                    .filter(|c| c.occupied_by.is_some())
-                    .count();
-                let mov_no = self
-                    .vertical_edges
-                    .as_slice()
-                    .ok_or_else(|| anyhow::anyhow!("No edge"))?
-                    .iter()
-                    .chain(
-                        self.horizontal_edges
-                            .as_slice()
-                            .ok_or_else(|| anyhow::anyhow!("No edge"))?
-                            .iter(),
-                    )
-                    .map(|e| match e {
-                        EdgeType::Occupied(o) => o.mov_no,
-                        EdgeType::Unoccupied(_) => 0,
-                    })
-                    .max()
-                    .unwrap_or_default()
-                    + 1;
-                let edge = self
-                    .horizontal_edges
-                    .as_slice_mut()
-                    .ok_or_else(|| anyhow::anyhow!("no elements"))?
-                    .iter_mut()
-                    .chain(
-                        self.vertical_edges
-                            .as_slice_mut()
-                            .ok_or_else(|| anyhow::anyhow!("no elements"))?
-                            .iter_mut(),
-                    )
-                    .find(|e| match e {
-                        EdgeType::Occupied(o) => o.id == mov.edge_id,
-                        EdgeType::Unoccupied(u) => u.id == mov.edge_id,
-                    })
-                    .ok_or_else(|| anyhow::anyhow!("Cant find edge"))?;
-                match edge {
-                    EdgeType::Occupied(_) => return Err(anyhow::anyhow!("Edge already occupied")),
-                    EdgeType::Unoccupied(_) => {
-                        *edge = EdgeType::Occupied(Occupied {
-                            mov_no,
-                            occupied_by: player_id.into(),
-                            id: mov.edge_id,
-                        })
-                    }
-                }
-                let new_cell_count = self
-                    .get_cells()
-                    .iter()
-                    .filter(|c| c.occupied_by.is_some())
-                    .count();
-                if new_cell_count <= previous_cell_count {
+                if !self.occupy(player_id, mov.edge_id)? {
                     if let Some(player) = self.get_next_turn_player(players) {
                         self.change_turn(&player)
                     }
@@ -345,7 +443,7 @@ impl GameTrait for Boxes {
     }
 
     fn is_game_end(&self, players: &[GamePlayer]) -> bool {
-        players.iter().filter(|p| p.send_channel.is_some()).count() <= 1
+        players.iter().filter(|p| p.connected).count() <= 1
             || self
                 .get_cells()
                 .iter()
@@ -353,36 +451,15 @@ impl GameTrait for Boxes {
     }
 
     fn start_game(data: Self::StartMessage, players: &[GamePlayer], _player_id: &str) -> Self {
-        let mut id = 0;
-        let horizontal_edges = Array2::<EdgeType>::from_shape_fn(
-            (data.board_height as usize, (data.board_width + 1) as usize),
-            |_| {
-                id += 1;
-                EdgeType::Unoccupied(Unoccupied { id })
-            },
-        );
-        let vertical_edges = Array2::<EdgeType>::from_shape_fn(
-            ((data.board_height + 1) as usize, data.board_width as usize),
-            |_| {
-                id += 1;
-                EdgeType::Unoccupied(Unoccupied { id })
-            },
-        );
         use rand::seq::SliceRandom;
-        let boxx = Self {
-            horizontal_edges,
-            vertical_edges,
-            turn: players
+        Self::new(
+            data.board_width,
+            data.board_height,
+            players
                 .choose(&mut rand::thread_rng())
                 .map(|p| p.player.id.clone())
                 .unwrap_or_default(),
-        };
-
-        boxx
-    }
-
-    fn input_handler(room_id: String, player_id: String) -> Self::InputHandler {
-        Self::InputHandler { room_id, player_id }
+        )
     }
 
     fn create_player_data(
@@ -390,117 +467,18 @@ impl GameTrait for Boxes {
         players: &[Player],
         player_id: &str,
     ) -> Self::PlayerGameData {
-        let player_idx = players
+        let sides = Player::sides(players);
+        let side_idx = players
             .iter()
-            .position(|p| p.id == player_id)
+            .find(|p| p.id == player_id)
+            .and_then(|player| sides.iter().position(|s| s.is_teammate(player)))
             .unwrap_or_default();
-        let max_players = players.len();
-        let c = (player_idx * 360) as f32 / (max_players as f32);
+        let max_sides = sides.len();
+        let c = (side_idx * 360) as f32 / (max_sides as f32);
         let color = colors_transform::Hsl::from(c, 100.0, 50.0);
         BoxesPlayerData {
             color: color.to_rgb().to_css_hex_string(),
+            player_id: player_id.into(),
         }
     }
 }
-
-#[ComplexObject]
-impl BoxesPlayerData {
-    pub async fn score<'ctx>(
-        &self,
-        ctx: &Context<'_>,
-        room_id: String,
-    ) -> Result<u32, async_graphql::Error> {
-        let data = ctx.data::<Storage>()?;
-        log::info!("Trying to Get read for room board");
-        let rooms = data.private_rooms.read().await;
-        log::info!("Got read for room board");
-        let room = rooms.get(&room_id).ok_or("Room Not found")?;
-
-        let game_data = &room.state.as_game().ok_or("Not game")?;
-        let game = &game_data.game;
-        let boxes = &game.as_boxes().ok_or("Not Bingo")?;
-        let player = game_data
-            .players
-            .iter()
-            .find(|p| {
-                if let Some(b) = p.data.as_boxes_player_data() {
-                    b.color == self.color
-                } else {
-                    false
-                }
-            })
-            .ok_or("Cant find player")?;
-        Ok(boxes.get_score(&player.player.id))
-    }
-}
-
-#[Object]
-impl BoxesInputs {
-    pub async fn start_game<'ctx>(
-        &self,
-        ctx: &Context<'_>,
-        board_width: u32,
-        board_height: u32,
-    ) -> Result<bool, async_graphql::Error> {
-        let room = {
-            let data = ctx.data::<Storage>()?;
-
-            let mut rooms = data.private_rooms.write().await;
-            let room = rooms
-                .get_mut(&self.room_id)
-                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
-            room.handle_player_message(
-                &self.player_id,
-                PlayerEvents::StartGame(StartMessages::BoxesStart(BoxesStart {
-                    board_width,
-                    board_height,
-                })),
-            )
-            .await?;
-            room.clone()
-        };
-
-        room.clone()
-            .state
-            .broadcast(ServerResponse::GameMessage(GameMessage {
-                event: GameEvents::GameStarted(GameStarted {
-                    game: room.state.as_game().ok_or("Not game")?.game.clone(),
-                }),
-                room: room.clone(),
-            }))
-            .await;
-        Ok(true)
-    }
-
-    pub async fn player_move<'ctx>(
-        &self,
-        ctx: &Context<'_>,
-        edge_id: u32,
-    ) -> Result<bool, async_graphql::Error> {
-        let data = ctx.data::<Storage>()?;
-        let room = {
-            let mut rooms = data.private_rooms.write().await;
-
-            let room = rooms
-                .get_mut(&self.room_id)
-                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
-            room.handle_player_message(
-                &self.player_id,
-                PlayerEvents::GameMessage(PlayerMessages::BoxesPlayerMessages(
-                    BoxesPlayerMessages::Move(Move { edge_id }),
-                )),
-            )
-            .await?;
-            room.clone()
-        };
-
-        room.clone()
-            .state
-            .broadcast(ServerResponse::GameMessage(GameMessage {
-                event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
-                room: room.clone(),
-            }))
-            .await;
-        Ok(true)
-    }
-}
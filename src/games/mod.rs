@@ -1,39 +1,65 @@
-use crate::{
-    data::{Player, Rank},
-    logic::GamePlayer,
-};
+use std::ops::RangeInclusive;
 
 use self::{
-    bingo::{Bingo, BingoInputs, BingoPlayerData, BingoPlayerMessages, BingoStart},
-    bluff::{Bluff, BluffPlayerData, BluffPlayerMessages, StartBluff, BluffInputs},
-    boxes::{Boxes, BoxesInputs, BoxesPlayerData, BoxesPlayerMessages, BoxesStart},
+    bingo::{Bingo, BingoPlayerData, BingoPlayerMessages, BingoStart},
+    bluff::{Bluff, BluffPlayerData, BluffPlayerMessages, StartBluff},
+    boxes::{Boxes, BoxesPlayerData, BoxesPlayerMessages, BoxesStart},
+    tambola::{Tambola, TambolaPlayerData, TambolaPlayerMessages, TambolaStart},
 };
 
-use async_graphql::{Context, Object, ObjectType, Union};
+#[cfg(feature = "graphql")]
+use async_graphql::{ComplexObject, Context, Enum, SimpleObject, Union};
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 pub mod bingo;
 pub mod bluff;
 pub mod boxes;
+pub mod tambola;
 
-#[derive(Clone, Serialize, Union)]
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "graphql", derive(Union))]
 pub enum Game {
     Bingo(Bingo),
     Boxes(Boxes),
     Bluff(Bluff),
+    Tambola(Tambola),
 }
 
 pub enum PlayerMessages {
     BingoMessages(BingoPlayerMessages),
     BoxesPlayerMessages(BoxesPlayerMessages),
     BluffPlayerMessages(BluffPlayerMessages),
+    TambolaPlayerMessages(TambolaPlayerMessages),
 }
 
 pub enum StartMessages {
     BingoStart(BingoStart),
     BoxesStart(BoxesStart),
-    BluffStart(StartBluff)
+    BluffStart(StartBluff),
+    TambolaStart(TambolaStart),
+}
+
+impl StartMessages {
+    pub fn kind(&self) -> GameKind {
+        match self {
+            StartMessages::BingoStart(_) => GameKind::Bingo,
+            StartMessages::BoxesStart(_) => GameKind::Boxes,
+            StartMessages::BluffStart(_) => GameKind::Bluff,
+            StartMessages::TambolaStart(_) => GameKind::Tambola,
+        }
+    }
+}
+
+impl GameKind {
+    pub fn player_range(&self) -> RangeInclusive<usize> {
+        match self {
+            GameKind::Bingo => Bingo::player_range(),
+            GameKind::Boxes => Boxes::player_range(),
+            GameKind::Bluff => Bluff::player_range(),
+            GameKind::Tambola => Tambola::player_range(),
+        }
+    }
 }
 
 impl PlayerMessages {
@@ -84,9 +110,26 @@ impl PlayerMessages {
             Err(self)
         }
     }
+
+    pub fn try_into_tambola_player_messages(self) -> Result<TambolaPlayerMessages, Self> {
+        if let Self::TambolaPlayerMessages(v) = self {
+            Ok(v)
+        } else {
+            Err(self)
+        }
+    }
 }
 
 impl Game {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Game::Bingo(_) => "bingo",
+            Game::Boxes(_) => "boxes",
+            Game::Bluff(_) => "bluff",
+            Game::Tambola(_) => "tambola",
+        }
+    }
+
     pub fn as_bingo(&self) -> Option<&Bingo> {
         if let Self::Bingo(v) = self {
             Some(v)
@@ -102,6 +145,14 @@ impl Game {
             None
         }
     }
+
+    pub fn as_tambola(&self) -> Option<&Tambola> {
+        if let Self::Tambola(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
 }
 
 pub trait GameTrait
@@ -111,13 +162,29 @@ where
     type PlayerMessage;
     type StartMessage;
     type PlayerGameData;
-    type InputHandler: ObjectType;
 
+    /// Number of players the game can be started with. [`Game`] accepts any count and
+    /// leaves the check to [`GameKind::player_range`].
+    fn player_range() -> RangeInclusive<usize> {
+        1..=usize::MAX
+    }
     fn is_game_running(&self) -> bool;
     fn can_change_turn(&self, player_id: &str) -> bool;
     fn get_rankings(&self, players: &[GamePlayer]) -> Vec<Rank>;
+    fn get_team_rankings(&self, players: &[GamePlayer]) -> Vec<TeamRank> {
+        TeamRank::from_player_ranks(&self.get_rankings(players))
+    }
     fn get_next_turn_player(&self, players: &[GamePlayer]) -> Option<String>;
+    /// Points the player scored so far, for games where moves earn points.
+    fn points_scored(&self, _player_id: &str) -> Option<u32> {
+        None
+    }
     fn change_turn(&mut self, player_id: &str);
+    /// Reverts the last accepted move and gives the turn back to whoever made it, returning
+    /// their id. Timed draws were made by no one and return an empty id.
+    fn undo_last_move(&mut self, _players: &mut [GamePlayer]) -> Result<String, anyhow::Error> {
+        Err(anyhow::anyhow!("Undo not supported"))
+    }
     fn handle_player_message(
         &mut self,
         player_id: &str,
@@ -125,26 +192,35 @@ where
         message: Self::PlayerMessage,
     ) -> Result<(), anyhow::Error>;
     fn is_game_end(&self, players: &[GamePlayer]) -> bool;
+    /// Milliseconds since the epoch when [`GameTrait::handle_deadline`] should run.
+    fn deadline(&self) -> Option<u64> {
+        None
+    }
+    /// Moves the deadline back, for time spent paused.
+    fn extend_deadline(&mut self, _by: u64) {}
+    /// Applies whatever the game does once its deadline passed.
+    fn handle_deadline(&mut self, _players: &mut [GamePlayer]) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
     fn start_game(data: Self::StartMessage, players: &[GamePlayer], player_id: &str) -> Self;
     fn create_player_data(
         data: &Self::StartMessage,
         players: &[Player],
         player_id: &str,
     ) -> Self::PlayerGameData;
-    fn input_handler(room_id: String, player_id: String) -> Self::InputHandler;
 }
 
 impl GameTrait for Game {
     type PlayerMessage = PlayerMessages;
     type StartMessage = StartMessages;
     type PlayerGameData = PlayerGameData;
-    type InputHandler = GameInputs;
 
     fn is_game_running(&self) -> bool {
         match self {
             Game::Bingo(b) => b.is_game_running(),
             Game::Boxes(b) => b.is_game_running(),
             Game::Bluff(b) => b.is_game_running(),
+            Game::Tambola(b) => b.is_game_running(),
         }
     }
 
@@ -153,6 +229,7 @@ impl GameTrait for Game {
             Game::Bingo(b) => b.can_change_turn(player_id),
             Game::Boxes(b) => b.can_change_turn(player_id),
             Game::Bluff(b) => b.can_change_turn(player_id),
+            Game::Tambola(b) => b.can_change_turn(player_id),
         }
     }
 
@@ -161,6 +238,16 @@ impl GameTrait for Game {
             Game::Bingo(b) => b.get_rankings(players),
             Game::Boxes(b) => b.get_rankings(players),
             Game::Bluff(b) => b.get_rankings(players),
+            Game::Tambola(b) => b.get_rankings(players),
+        }
+    }
+
+    fn get_team_rankings(&self, players: &[GamePlayer]) -> Vec<TeamRank> {
+        match self {
+            Game::Bingo(b) => b.get_team_rankings(players),
+            Game::Boxes(b) => b.get_team_rankings(players),
+            Game::Bluff(b) => b.get_team_rankings(players),
+            Game::Tambola(b) => b.get_team_rankings(players),
         }
     }
 
@@ -169,6 +256,16 @@ impl GameTrait for Game {
             Game::Bingo(b) => b.get_next_turn_player(players),
             Game::Boxes(b) => b.get_next_turn_player(players),
             Game::Bluff(b) => b.get_next_turn_player(players),
+            Game::Tambola(b) => b.get_next_turn_player(players),
+        }
+    }
+
+    fn points_scored(&self, player_id: &str) -> Option<u32> {
+        match self {
+            Game::Bingo(b) => b.points_scored(player_id),
+            Game::Boxes(b) => b.points_scored(player_id),
+            Game::Bluff(b) => b.points_scored(player_id),
+            Game::Tambola(b) => b.points_scored(player_id),
         }
     }
 
@@ -177,6 +274,16 @@ impl GameTrait for Game {
             Game::Bingo(b) => b.change_turn(player_id),
             Game::Boxes(b) => b.change_turn(player_id),
             Game::Bluff(b) => b.change_turn(player_id),
+            Game::Tambola(b) => b.change_turn(player_id),
+        }
+    }
+
+    fn undo_last_move(&mut self, players: &mut [GamePlayer]) -> Result<String, anyhow::Error> {
+        match self {
+            Game::Bingo(b) => b.undo_last_move(players),
+            Game::Boxes(b) => b.undo_last_move(players),
+            Game::Bluff(b) => b.undo_last_move(players),
+            Game::Tambola(b) => b.undo_last_move(players),
         }
     }
 
@@ -208,6 +315,13 @@ impl GameTrait for Game {
                     Err(anyhow::anyhow!("Not Bluff message"))
                 }
             }
+            Game::Tambola(b) => {
+                if let Ok(message) = message.try_into_tambola_player_messages() {
+                    b.handle_player_message(player_id, players, message)
+                } else {
+                    Err(anyhow::anyhow!("Not Tambola message"))
+                }
+            }
         }
     }
 
@@ -217,6 +331,34 @@ impl GameTrait for Game {
 
             Game::Boxes(b) => b.is_game_end(players),
             Game::Bluff(b) => b.is_game_end(players),
+            Game::Tambola(b) => b.is_game_end(players),
+        }
+    }
+
+    fn deadline(&self) -> Option<u64> {
+        match self {
+            Game::Bingo(b) => b.deadline(),
+            Game::Boxes(b) => b.deadline(),
+            Game::Bluff(b) => b.deadline(),
+            Game::Tambola(b) => b.deadline(),
+        }
+    }
+
+    fn extend_deadline(&mut self, by: u64) {
+        match self {
+            Game::Bingo(b) => b.extend_deadline(by),
+            Game::Boxes(b) => b.extend_deadline(by),
+            Game::Bluff(b) => b.extend_deadline(by),
+            Game::Tambola(b) => b.extend_deadline(by),
+        }
+    }
+
+    fn handle_deadline(&mut self, players: &mut [GamePlayer]) -> Result<(), anyhow::Error> {
+        match self {
+            Game::Bingo(b) => b.handle_deadline(players),
+            Game::Boxes(b) => b.handle_deadline(players),
+            Game::Bluff(b) => b.handle_deadline(players),
+            Game::Tambola(b) => b.handle_deadline(players),
         }
     }
 
@@ -231,13 +373,12 @@ impl GameTrait for Game {
             StartMessages::BluffStart(data) => {
                 Game::Bluff(Bluff::start_game(data, players, player_id))
             }
+            StartMessages::TambolaStart(data) => {
+                Game::Tambola(Tambola::start_game(data, players, player_id))
+            }
         }
     }
 
-    fn input_handler(room_id: String, player_id: String) -> Self::InputHandler {
-        GameInputs { room_id, player_id }
-    }
-
     fn create_player_data(
         data: &Self::StartMessage,
         players: &[Player],
@@ -254,15 +395,20 @@ impl GameTrait for Game {
             StartMessages::BluffStart(data) => {
                 PlayerGameData::BluffPlayerData(Bluff::create_player_data(data, players, player_id))
             }
+            StartMessages::TambolaStart(data) => PlayerGameData::TambolaPlayerData(
+                Tambola::create_player_data(data, players, player_id),
+            ),
         }
     }
 }
 
-#[derive(Clone, Serialize, Union)]
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "graphql", derive(Union))]
 pub enum PlayerGameData {
     BingoPlayerData(BingoPlayerData),
     BoxesPlayerData(BoxesPlayerData),
     BluffPlayerData(BluffPlayerData),
+    TambolaPlayerData(TambolaPlayerData),
 }
 
 impl PlayerGameData {
@@ -296,42 +442,123 @@ impl PlayerGameData {
             None
         }
     }
+
+    pub fn as_tambola_player_data(&self) -> Option<&TambolaPlayerData> {
+        if let Self::TambolaPlayerData(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject), graphql(complex))]
+pub struct GamePlayer {
+    pub player: Player,
+    pub data: PlayerGameData,
+
+    /// Set while the player is subscribed to the room. Games skip disconnected players.
+    #[serde(skip_serializing)]
+    #[cfg_attr(feature = "graphql", graphql(skip))]
+    pub connected: bool,
+}
+
+#[cfg(feature = "graphql")]
+#[ComplexObject]
+impl GamePlayer {
+    pub async fn is_connected(&self, _ctx: &Context<'_>) -> Result<bool, async_graphql::Error> {
+        Ok(self.connected)
+    }
 }
 
-pub struct GameInputs {
-    pub room_id: String,
-    pub player_id: String,
+#[derive(Serialize, Clone, Debug)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct Player {
+    pub id: String,
+    pub name: String,
+    pub team: Option<u32>,
 }
 
-#[Object]
-impl GameInputs {
-    pub async fn bingo_inputs<'ctx>(
-        &self,
-        _ctx: &Context<'_>,
-    ) -> Result<BingoInputs, async_graphql::Error> {
-        Ok(BingoInputs {
-            room_id: self.room_id.clone(),
-            player_id: self.player_id.clone(),
-        })
-    }
-    pub async fn boxes_inputs<'ctx>(
-        &self,
-        _ctx: &Context<'_>,
-    ) -> Result<BoxesInputs, async_graphql::Error> {
-        Ok(BoxesInputs {
-            room_id: self.room_id.clone(),
-            player_id: self.player_id.clone(),
-        })
-    }
-
-
-    pub async fn bluff_inputs<'ctx>(
-        &self,
-        _ctx: &Context<'_>,
-    ) -> Result<BluffInputs, async_graphql::Error> {
-        Ok(BluffInputs {
-            room_id: self.room_id.clone(),
-            player_id: self.player_id.clone(),
-        })
+impl Player {
+    /// Returns `true` if both players play for the same side.
+    pub fn is_teammate(&self, other: &Player) -> bool {
+        self.id == other.id || (self.team.is_some() && self.team == other.team)
+    }
+
+    /// Returns the first player of every side, in the order the sides appear.
+    pub fn sides<'a>(players: impl IntoIterator<Item = &'a Player>) -> Vec<&'a Player> {
+        let mut sides: Vec<&Player> = vec![];
+        for player in players {
+            if !sides.iter().any(|s| s.is_teammate(player)) {
+                sides.push(player);
+            }
+        }
+        sides
     }
 }
+
+#[derive(Serialize, Clone)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct Rank {
+    pub rank: u32,
+    pub player: Player,
+}
+
+/// Rank of a side, which is either a team or a single player playing without one.
+#[derive(Serialize, Clone)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct TeamRank {
+    pub rank: u32,
+    pub team: Option<u32>,
+    pub players: Vec<Player>,
+}
+
+impl TeamRank {
+    /// Ranks every side by the best rank any of its players achieved.
+    pub fn from_player_ranks(ranks: &[Rank]) -> Vec<TeamRank> {
+        let mut ordered = ranks.iter().collect::<Vec<_>>();
+        ordered.sort_by_key(|r| r.rank);
+        let mut team_ranks: Vec<(u32, TeamRank)> = vec![];
+        for r in ordered {
+            if let Some((_, team_rank)) = team_ranks
+                .iter_mut()
+                .find(|(_, t)| t.players.iter().any(|p| p.is_teammate(&r.player)))
+            {
+                team_rank.players.push(r.player.clone());
+            } else {
+                team_ranks.push((
+                    r.rank,
+                    TeamRank {
+                        rank: 0,
+                        team: r.player.team,
+                        players: vec![r.player.clone()],
+                    },
+                ));
+            }
+        }
+        let mut rank = 0;
+        let mut last_best = None;
+        team_ranks
+            .into_iter()
+            .map(|(best, mut team_rank)| {
+                if last_best != Some(best) {
+                    rank += 1;
+                    last_best = Some(best);
+                }
+                team_rank.rank = rank;
+                team_rank
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "graphql", derive(Enum))]
+#[serde(rename_all = "camelCase")]
+pub enum GameKind {
+    Bingo,
+    Boxes,
+    Bluff,
+    Tambola,
+}
@@ -0,0 +1,352 @@
+use std::{cmp::Reverse, ops::RangeInclusive};
+
+#[cfg(feature = "graphql")]
+use async_graphql::{Enum, SimpleObject};
+use rand::{
+    seq::{IteratorRandom, SliceRandom},
+    Rng,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::now_millis;
+
+use super::{
+    bingo::{CallerMode, Cell, SelectedCell},
+    GamePlayer, GameTrait, Player, Rank,
+};
+
+/// Tambola, or Housie: every player gets a ticket, numbers from 1 to 90 are called and the
+/// first player to claim each prize wins it.
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct Tambola {
+    /// Player who started the game, and draws the numbers without a timer.
+    pub caller: String,
+    pub caller_mode: CallerMode,
+    pub called: Vec<SelectedCell>,
+    /// Milliseconds since the epoch of the next draw, when numbers are drawn on a timer.
+    pub next_call_at: Option<u64>,
+    /// Prizes won so far, in claim order.
+    pub prizes: Vec<PrizeClaim>,
+    /// Players out of the game after a false claim.
+    pub disqualified: Vec<String>,
+}
+
+pub enum TambolaPlayerMessages {
+    /// Draws the next number.
+    CallNext,
+    Claim(Prize),
+}
+
+pub struct TambolaStart {
+    pub caller_mode: CallerMode,
+}
+
+impl TambolaStart {
+    pub fn new(call_interval: Option<u64>) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            caller_mode: CallerMode::with_balls(Ticket::BALLS, call_interval)?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "graphql", derive(Enum))]
+#[serde(rename_all = "camelCase")]
+pub enum Prize {
+    /// Any five numbers of the ticket.
+    EarlyFive,
+    TopLine,
+    MiddleLine,
+    BottomLine,
+    /// First and last numbers of the top and bottom lines.
+    Corners,
+    /// Every number of the ticket.
+    FullHouse,
+}
+
+#[derive(Serialize, Clone)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct PrizeClaim {
+    pub prize: Prize,
+    pub player_id: String,
+    /// Milliseconds since the epoch when the prize was claimed.
+    pub claimed_at: u64,
+}
+
+#[derive(Serialize, Clone)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct TambolaPlayerData {
+    pub ticket: Ticket,
+}
+
+impl GameTrait for Tambola {
+    type PlayerMessage = TambolaPlayerMessages;
+    type StartMessage = TambolaStart;
+    type PlayerGameData = TambolaPlayerData;
+
+    fn player_range() -> RangeInclusive<usize> {
+        2..=16
+    }
+
+    fn is_game_running(&self) -> bool {
+        true
+    }
+
+    fn can_change_turn(&self, _player_id: &str) -> bool {
+        false
+    }
+
+    /// Ranks the Full House winner first, then everyone by prizes won and numbers called
+    /// on their ticket. Disqualified players come last.
+    fn get_rankings(&self, players: &[GamePlayer]) -> Vec<Rank> {
+        let mut standings = players
+            .iter()
+            .map(|p| (self.standing(p), p))
+            .collect::<Vec<_>>();
+        standings.sort_by_key(|(standing, _)| Reverse(*standing));
+
+        let mut rank = 0;
+        let mut last = None;
+        standings
+            .into_iter()
+            .map(|(standing, p)| {
+                if last != Some(standing) {
+                    rank += 1;
+                    last = Some(standing);
+                }
+                Rank {
+                    rank,
+                    player: p.player.clone(),
+                }
+            })
+            .collect()
+    }
+
+    fn get_next_turn_player(&self, _players: &[GamePlayer]) -> Option<String> {
+        None
+    }
+
+    fn change_turn(&mut self, _player_id: &str) {}
+
+    fn handle_player_message(
+        &mut self,
+        player_id: &str,
+        players: &mut [GamePlayer],
+        message: Self::PlayerMessage,
+    ) -> Result<(), anyhow::Error> {
+        match message {
+            TambolaPlayerMessages::CallNext => {
+                if self.caller != player_id {
+                    Err(anyhow::anyhow!("Only {} can call numbers", self.caller))
+                } else if self.next_call_at.is_some() {
+                    Err(anyhow::anyhow!("Numbers are drawn on a timer"))
+                } else {
+                    self.next_call_at = self.caller_mode.draw(&mut self.called, player_id)?;
+                    Ok(())
+                }
+            }
+            TambolaPlayerMessages::Claim(prize) => {
+                if self.disqualified.iter().any(|p| p == player_id) {
+                    return Err(anyhow::anyhow!("Disqualified after a false claim"));
+                }
+                if self.prizes.iter().any(|c| c.prize == prize) {
+                    return Err(anyhow::anyhow!("Prize already won"));
+                }
+                let won = players
+                    .iter()
+                    .find(|p| p.player.id == player_id)
+                    .and_then(|p| p.data.as_tambola_player_data())
+                    .ok_or_else(|| anyhow::anyhow!("Player not found"))?
+                    .ticket
+                    .has_prize(prize, &self.called);
+                if won {
+                    self.prizes.push(PrizeClaim {
+                        prize,
+                        player_id: player_id.into(),
+                        claimed_at: now_millis(),
+                    });
+                } else {
+                    self.disqualified.push(player_id.into());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn is_game_end(&self, players: &[GamePlayer]) -> bool {
+        self.prizes.iter().any(|c| c.prize == Prize::FullHouse)
+            || self.called.len() >= self.caller_mode.balls as usize
+            || players
+                .iter()
+                .filter(|p| p.connected && !self.disqualified.contains(&p.player.id))
+                .count()
+                <= 1
+    }
+
+    fn deadline(&self) -> Option<u64> {
+        self.next_call_at
+    }
+
+    fn extend_deadline(&mut self, by: u64) {
+        self.next_call_at = self.next_call_at.map(|d| d + by);
+    }
+
+    /// Draws the next number once it is due.
+    fn handle_deadline(&mut self, _players: &mut [GamePlayer]) -> Result<(), anyhow::Error> {
+        self.next_call_at = self.caller_mode.draw(&mut self.called, "")?;
+        Ok(())
+    }
+
+    fn start_game(data: Self::StartMessage, _: &[GamePlayer], player_id: &str) -> Self {
+        Self {
+            caller: player_id.into(),
+            next_call_at: data
+                .caller_mode
+                .call_interval
+                .map(|interval| now_millis() + interval),
+            caller_mode: data.caller_mode,
+            called: vec![],
+            prizes: vec![],
+            disqualified: vec![],
+        }
+    }
+
+    fn create_player_data(
+        _data: &Self::StartMessage,
+        _players: &[Player],
+        _player_id: &str,
+    ) -> Self::PlayerGameData {
+        TambolaPlayerData {
+            ticket: Ticket::random(&mut rand::thread_rng()),
+        }
+    }
+}
+
+impl Tambola {
+    /// What a player is ranked by, higher is better: not being disqualified, winning the
+    /// Full House, prizes won and numbers called on their ticket.
+    fn standing(&self, player: &GamePlayer) -> (bool, bool, usize, usize) {
+        let won = self
+            .prizes
+            .iter()
+            .filter(|c| c.player_id == player.player.id)
+            .collect::<Vec<_>>();
+        let marked = player
+            .data
+            .as_tambola_player_data()
+            .map(|d| d.ticket.marked(&self.called))
+            .unwrap_or(0);
+        (
+            !self.disqualified.contains(&player.player.id),
+            won.iter().any(|c| c.prize == Prize::FullHouse),
+            won.len(),
+            marked,
+        )
+    }
+}
+
+/// A ticket of 3 rows and 9 columns holding 15 numbers. Every row has 5 numbers and every
+/// column between 1 and 3, taken from the column's range and sorted top to bottom.
+#[derive(Serialize, Clone, Debug)]
+#[cfg_attr(feature = "graphql", derive(SimpleObject))]
+pub struct Ticket {
+    /// Rows of the ticket, with `null` for the blank cells.
+    pub rows: Vec<Vec<Option<Cell>>>,
+}
+
+impl Ticket {
+    pub const ROWS: usize = 3;
+    pub const COLUMNS: usize = 9;
+    pub const NUMBERS: usize = 15;
+    /// Numbers that can be called.
+    pub const BALLS: Cell = 90;
+
+    /// Numbers column `column` takes from: 1-9, 10-19 and so on up to 80-90.
+    pub fn column_range(column: usize) -> RangeInclusive<Cell> {
+        let start = (column as Cell * 10).max(1);
+        let end = if column + 1 == Self::COLUMNS {
+            Self::BALLS
+        } else {
+            column as Cell * 10 + 9
+        };
+        start..=end
+    }
+
+    pub fn random(rng: &mut impl Rng) -> Self {
+        // Every column holds a number, and the other six go to columns with room left
+        let mut counts = [1; Self::COLUMNS];
+        for _ in Self::COLUMNS..Self::NUMBERS {
+            let column = (0..Self::COLUMNS)
+                .filter(|c| counts[*c] < Self::ROWS)
+                .choose(rng)
+                .expect("columns have room for 27 numbers");
+            counts[column] += 1;
+        }
+
+        // Each column fills the rows still missing the most numbers, which always leaves
+        // every row with 5
+        let mut missing = [Self::NUMBERS / Self::ROWS; Self::ROWS];
+        let mut rows = vec![vec![None; Self::COLUMNS]; Self::ROWS];
+        for (column, count) in counts.iter().enumerate() {
+            let mut free = (0..Self::ROWS).collect::<Vec<_>>();
+            free.shuffle(rng);
+            free.sort_by_key(|row| Reverse(missing[*row]));
+            let mut picked = free[..*count].to_vec();
+            picked.sort_unstable();
+
+            let mut numbers = Self::column_range(column).choose_multiple(rng, *count);
+            numbers.sort_unstable();
+            for (row, number) in picked.into_iter().zip(numbers) {
+                rows[row][column] = Some(number);
+                missing[row] -= 1;
+            }
+        }
+        Self { rows }
+    }
+
+    /// Numbers of the ticket, row by row.
+    pub fn numbers(&self) -> impl Iterator<Item = Cell> + '_ {
+        self.rows.iter().flatten().flatten().copied()
+    }
+
+    /// Numbers a prize needs called, or any five of them for [`Prize::EarlyFive`].
+    pub fn prize_numbers(&self, prize: Prize) -> Vec<Cell> {
+        let row = |index: usize| {
+            self.rows
+                .get(index)
+                .map(|row| row.iter().flatten().copied().collect::<Vec<_>>())
+                .unwrap_or_default()
+        };
+        match prize {
+            Prize::EarlyFive | Prize::FullHouse => self.numbers().collect(),
+            Prize::TopLine => row(0),
+            Prize::MiddleLine => row(1),
+            Prize::BottomLine => row(Self::ROWS - 1),
+            Prize::Corners => [row(0), row(Self::ROWS - 1)]
+                .iter()
+                .flat_map(|row| row.first().into_iter().chain(row.last()).copied())
+                .collect(),
+        }
+    }
+
+    /// Number of the ticket's numbers that were called.
+    pub fn marked(&self, called: &[SelectedCell]) -> usize {
+        self.numbers()
+            .filter(|n| called.iter().any(|c| c.cell_value == *n))
+            .count()
+    }
+
+    /// Returns `true` if the called numbers complete the prize on this ticket.
+    pub fn has_prize(&self, prize: Prize, called: &[SelectedCell]) -> bool {
+        let numbers = self.prize_numbers(prize);
+        let marked = numbers
+            .iter()
+            .filter(|n| called.iter().any(|c| c.cell_value == **n))
+            .count();
+        match prize {
+            Prize::EarlyFive => marked >= 5,
+            _ => !numbers.is_empty() && marked == numbers.len(),
+        }
+    }
+}
@@ -0,0 +1,702 @@
+//! GraphQL handlers that apply game moves to a room, and the game fields that need the
+//! room to resolve.
+
+use async_graphql::{ComplexObject, Context, Object, Result};
+
+use crate::{
+    data::{GameMessage, ServerResponse, Storage},
+    games::{
+        bingo::{BingoPlayerMessages, CallerMode, ClaimPenalty, NumberPool, WinPattern, WinRules},
+        bluff::{BluffPlayerMessages, Card, StartBluff},
+        boxes::{BoxesPlayerData, BoxesPlayerMessages, BoxesStart, Move},
+        tambola::{Prize, TambolaPlayerMessages, TambolaStart},
+        PlayerMessages, StartMessages,
+    },
+    logic::{GameEvents, GameStarted, PlayerEvents, RoomUpdate},
+    ratelimit::{limit, Action},
+    schema::watch_game_deadline,
+};
+
+/// Tags the current resolver span with the room and player an input handler acts for.
+pub fn record_span(room_id: &str, player_id: &str) {
+    let span = tracing::Span::current();
+    span.record("room_id", room_id);
+    span.record("player_id", player_id);
+}
+
+pub struct GameInputs {
+    pub room_id: String,
+    pub player_id: String,
+}
+
+#[Object]
+impl GameInputs {
+    pub async fn bingo_inputs(
+        &self,
+        _ctx: &Context<'_>,
+    ) -> Result<BingoInputs, async_graphql::Error> {
+        Ok(BingoInputs {
+            room_id: self.room_id.clone(),
+            player_id: self.player_id.clone(),
+        })
+    }
+    pub async fn boxes_inputs(
+        &self,
+        _ctx: &Context<'_>,
+    ) -> Result<BoxesInputs, async_graphql::Error> {
+        Ok(BoxesInputs {
+            room_id: self.room_id.clone(),
+            player_id: self.player_id.clone(),
+        })
+    }
+
+    pub async fn bluff_inputs(
+        &self,
+        _ctx: &Context<'_>,
+    ) -> Result<BluffInputs, async_graphql::Error> {
+        Ok(BluffInputs {
+            room_id: self.room_id.clone(),
+            player_id: self.player_id.clone(),
+        })
+    }
+
+    pub async fn tambola_inputs(
+        &self,
+        _ctx: &Context<'_>,
+    ) -> Result<TambolaInputs, async_graphql::Error> {
+        Ok(TambolaInputs {
+            room_id: self.room_id.clone(),
+            player_id: self.player_id.clone(),
+        })
+    }
+}
+
+pub struct BingoInputs {
+    pub room_id: String,
+    pub player_id: String,
+}
+
+#[Object]
+impl BingoInputs {
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "bingo"))]
+    pub async fn start_game(
+        &self,
+        ctx: &Context<'_>,
+        board_size: u16,
+        pattern: Option<WinPattern>,
+        #[graphql(desc = "Cells of the custom pattern, row by row")] mask: Option<Vec<Vec<bool>>>,
+        lines_to_win: Option<u32>,
+        #[graphql(desc = "Draw numbers from a pool of this many balls instead of picking them")]
+        balls: Option<u32>,
+        #[graphql(desc = "Play with the numbers from 1 to this, `boardSize²` by default")]
+        pool_size: Option<u32>,
+        #[graphql(desc = "Labels of the numbers in order, for word or emoji Bingo")] labels: Option<
+            Vec<String>,
+        >,
+        #[graphql(desc = "Milliseconds between draws, otherwise the caller draws each number")]
+        call_interval: Option<u64>,
+        #[graphql(desc = "Make players claim their win, costing a false claim this penalty")]
+        claim_penalty: Option<ClaimPenalty>,
+        #[graphql(desc = "Cards each player plays at once, 1 by default")] cards: Option<u16>,
+    ) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let data = ctx.data::<Storage>()?;
+        data.bingo_board_sizes.check(board_size)?;
+        let win_rules = WinRules::new(pattern.unwrap_or_default(), mask, lines_to_win, board_size)?;
+        let pool = NumberPool::new(pool_size.or(balls), labels, board_size)?;
+        let caller_mode = CallerMode::new(balls, call_interval, &pool)?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::StartGame(StartMessages::BingoStart(data.bingo_start(
+                    board_size,
+                    win_rules,
+                    caller_mode,
+                    claim_penalty,
+                    cards,
+                    pool,
+                )?)),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::GameStarted(GameStarted {
+                game: room.state.as_game().ok_or("Not game")?.game.clone(),
+            }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        watch_game_deadline(data, &self.room_id, &room);
+        Ok(true)
+    }
+
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "bingo"))]
+    pub async fn ready_board(
+        &self,
+        ctx: &Context<'_>,
+        board: Option<Vec<Vec<u32>>>,
+        #[graphql(desc = "Boards for every card, when playing more than one")] boards: Option<
+            Vec<Vec<Vec<u32>>>,
+        >,
+        #[graphql(
+            desc = "Fill the empty cells of the boards, or all of them, with shuffled numbers"
+        )]
+        randomize: Option<bool>,
+    ) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let data = ctx.data::<Storage>()?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            let randomize = randomize.unwrap_or_default();
+            let boards = board
+                .into_iter()
+                .chain(boards.into_iter().flatten())
+                .collect::<Vec<_>>();
+            if boards.is_empty() && !randomize {
+                return Err("Board is required".into());
+            }
+            let boards = room
+                .state
+                .as_game()
+                .ok_or_else(|| async_graphql::Error::from("Game not running"))?
+                .game
+                .as_bingo()
+                .ok_or("Not Bingo")?
+                .make_boards(boards, randomize)?;
+
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::GameMessage(PlayerMessages::BingoMessages(
+                    BingoPlayerMessages::ReadyBoard(boards),
+                )),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        watch_game_deadline(data, &self.room_id, &room);
+        Ok(true)
+    }
+
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "bingo"))]
+    pub async fn player_move(
+        &self,
+        ctx: &Context<'_>,
+        number: u32,
+    ) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let data = ctx.data::<Storage>()?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::GameMessage(PlayerMessages::BingoMessages(
+                    BingoPlayerMessages::Move(number),
+                )),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+
+    /// Draws the next number in caller mode. Only the player who started the game can.
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "bingo"))]
+    pub async fn call_next(&self, ctx: &Context<'_>) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let data = ctx.data::<Storage>()?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::GameMessage(PlayerMessages::BingoMessages(
+                    BingoPlayerMessages::CallNext,
+                )),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+
+    /// Claims a completed board, in caller mode or when the game takes claims. A false claim
+    /// disqualifies the player or costs them a turn.
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "bingo"))]
+    pub async fn claim_bingo(&self, ctx: &Context<'_>) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let data = ctx.data::<Storage>()?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::GameMessage(PlayerMessages::BingoMessages(
+                    BingoPlayerMessages::ClaimBingo,
+                )),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+}
+
+#[ComplexObject]
+impl BoxesPlayerData {
+    #[tracing::instrument(skip(self, ctx), fields(game = "boxes"))]
+    pub async fn score(
+        &self,
+        ctx: &Context<'_>,
+        room_id: String,
+    ) -> Result<u32, async_graphql::Error> {
+        let data = ctx.data::<Storage>()?;
+        let rooms = data.read_rooms().await;
+        let room = rooms.get(&room_id).ok_or("Room Not found")?;
+
+        let game_data = &room.state.as_game().ok_or("Not game")?;
+        let game = &game_data.game;
+        let boxes = &game.as_boxes().ok_or("Not Bingo")?;
+        let player = game_data
+            .players
+            .iter()
+            .find(|p| p.player.id == self.player_id)
+            .ok_or("Cant find player")?;
+        Ok(boxes.get_score(&player.player.id))
+    }
+
+    #[tracing::instrument(skip(self, ctx), fields(game = "boxes"))]
+    pub async fn team_score(
+        &self,
+        ctx: &Context<'_>,
+        room_id: String,
+    ) -> Result<u32, async_graphql::Error> {
+        let data = ctx.data::<Storage>()?;
+        let rooms = data.read_rooms().await;
+        let room = rooms.get(&room_id).ok_or("Room Not found")?;
+
+        let game_data = &room.state.as_game().ok_or("Not game")?;
+        let boxes = &game_data.game.as_boxes().ok_or("Not Boxes")?;
+        let player = game_data
+            .players
+            .iter()
+            .find(|p| p.player.id == self.player_id)
+            .ok_or("Cant find player")?;
+        Ok(boxes.get_team_score(&game_data.players, &player.player))
+    }
+}
+
+pub struct BoxesInputs {
+    pub room_id: String,
+    pub player_id: String,
+}
+
+#[Object]
+impl BoxesInputs {
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "boxes"))]
+    pub async fn start_game(
+        &self,
+        ctx: &Context<'_>,
+        board_width: u32,
+        board_height: u32,
+    ) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let room = {
+            let data = ctx.data::<Storage>()?;
+
+            let mut rooms = data.write_rooms().await;
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::StartGame(StartMessages::BoxesStart(BoxesStart {
+                    board_width,
+                    board_height,
+                })),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::GameStarted(GameStarted {
+                game: room.state.as_game().ok_or("Not game")?.game.clone(),
+            }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "boxes"))]
+    pub async fn player_move(
+        &self,
+        ctx: &Context<'_>,
+        edge_id: u32,
+    ) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let data = ctx.data::<Storage>()?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::GameMessage(PlayerMessages::BoxesPlayerMessages(
+                    BoxesPlayerMessages::Move(Move { edge_id }),
+                )),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+}
+
+pub struct BluffInputs {
+    pub room_id: String,
+    pub player_id: String,
+}
+
+#[Object]
+impl BluffInputs {
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "bluff"))]
+    pub async fn start_game(
+        &self,
+        ctx: &Context<'_>,
+        seed: u64,
+    ) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let room = {
+            let data = ctx.data::<Storage>()?;
+
+            let mut rooms = data.write_rooms().await;
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::StartGame(StartMessages::BluffStart(StartBluff { seed })),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::GameStarted(GameStarted {
+                game: room.state.as_game().ok_or("Not game")?.game.clone(),
+            }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "bluff"))]
+    pub async fn pass(&self, ctx: &Context<'_>) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let data = ctx.data::<Storage>()?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::GameMessage(PlayerMessages::BluffPlayerMessages(
+                    BluffPlayerMessages::Pass,
+                )),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "bluff"))]
+    pub async fn flip(&self, ctx: &Context<'_>) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let data = ctx.data::<Storage>()?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::GameMessage(PlayerMessages::BluffPlayerMessages(
+                    BluffPlayerMessages::Flip,
+                )),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "bluff"))]
+    pub async fn vote_round_end(&self, ctx: &Context<'_>) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let data = ctx.data::<Storage>()?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::GameMessage(PlayerMessages::BluffPlayerMessages(
+                    BluffPlayerMessages::RaiseEndRound,
+                )),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "bluff"))]
+    pub async fn deal(
+        &self,
+        ctx: &Context<'_>,
+        cards: Vec<u8>,
+        claim: u8,
+    ) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let data = ctx.data::<Storage>()?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::GameMessage(PlayerMessages::BluffPlayerMessages(
+                    BluffPlayerMessages::Deal(
+                        cards.into_iter().map(Card::from).collect(),
+                        Card::from(claim),
+                    ),
+                )),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+}
+
+pub struct TambolaInputs {
+    pub room_id: String,
+    pub player_id: String,
+}
+
+#[Object]
+impl TambolaInputs {
+    /// Deals a ticket to every player. Without a `call_interval` the player who started
+    /// the game draws each number.
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "tambola"))]
+    pub async fn start_game(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "Milliseconds between draws, otherwise the caller draws each number")]
+        call_interval: Option<u64>,
+    ) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let data = ctx.data::<Storage>()?;
+        let start = TambolaStart::new(call_interval)?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::StartGame(StartMessages::TambolaStart(start)),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::GameStarted(GameStarted {
+                game: room.state.as_game().ok_or("Not game")?.game.clone(),
+            }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        watch_game_deadline(data, &self.room_id, &room);
+        Ok(true)
+    }
+
+    /// Draws the next number. Only the player who started the game can.
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "tambola"))]
+    pub async fn call_next(&self, ctx: &Context<'_>) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let data = ctx.data::<Storage>()?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::GameMessage(PlayerMessages::TambolaPlayerMessages(
+                    TambolaPlayerMessages::CallNext,
+                )),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+
+    /// Claims a prize not won yet. A false claim disqualifies the player.
+    #[tracing::instrument(skip_all, fields(room_id, player_id, game = "tambola"))]
+    pub async fn claim_prize(
+        &self,
+        ctx: &Context<'_>,
+        prize: Prize,
+    ) -> Result<bool, async_graphql::Error> {
+        record_span(&self.room_id, &self.player_id);
+        limit(ctx, Action::Move, &self.player_id)?;
+        let data = ctx.data::<Storage>()?;
+        let room = {
+            let mut rooms = data.write_rooms().await;
+
+            let room = rooms
+                .get_mut(&self.room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exis"))?;
+            room.handle_player_message(
+                &self.player_id,
+                PlayerEvents::GameMessage(PlayerMessages::TambolaPlayerMessages(
+                    TambolaPlayerMessages::Claim(prize),
+                )),
+            )
+            .await?;
+            room.clone()
+        };
+
+        room.broadcast(ServerResponse::GameMessage(GameMessage {
+            event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(true)
+    }
+}
@@ -0,0 +1,28 @@
+//! Game engines for BingoTingo and the server that hosts them.
+//!
+//! [`games`] holds the rules of every game and builds without any features. Enable
+//! `graphql` to derive the GraphQL types of the game state, `wasm` for JavaScript bindings
+//! to the rules, and `server` (the default) for the rooms, schema and listeners used by
+//! the `bingo-backend` binary.
+
+pub mod games;
+pub mod utils;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "server")]
+pub mod admin;
+#[cfg(feature = "server")]
+pub mod data;
+#[cfg(feature = "server")]
+pub mod inputs;
+#[cfg(feature = "server")]
+pub mod listen;
+#[cfg(feature = "server")]
+pub mod logic;
+#[cfg(feature = "server")]
+pub mod ratelimit;
+#[cfg(feature = "server")]
+pub mod schema;
+#[cfg(feature = "server")]
+pub mod ws;
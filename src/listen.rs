@@ -0,0 +1,326 @@
+//! Listeners for the HTTP server: TCP sockets with optional TLS, and a Unix domain socket.
+
+use std::{
+    convert::Infallible,
+    fs::File,
+    io::BufReader,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures::future::BoxFuture;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+};
+use tokio_rustls::{
+    rustls::{Certificate, PrivateKey, ServerConfig},
+    TlsAcceptor,
+};
+use warp::hyper::{self, server::conn::Http, service::Service, Body, Request, Response};
+
+/// Address of the connected peer, attached to every request as an extension since
+/// connections are accepted here rather than by warp.
+#[derive(Clone, Copy, Debug)]
+pub struct PeerAddr(pub SocketAddr);
+
+#[derive(Clone, Debug)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(Clone, Debug)]
+pub struct ListenConfig {
+    pub bind_addrs: Vec<SocketAddr>,
+    pub unix_socket: Option<PathBuf>,
+    /// Serve TLS on every TCP listener.
+    pub tls: Option<TlsFiles>,
+}
+
+impl ListenConfig {
+    /// `BIND_ADDRS` is a comma separated list of addresses, with or without a port
+    /// (`0.0.0.0:8000,[::1]`). Entries without a port and the default `0.0.0.0` use `PORT`.
+    pub fn from_env() -> Result<Self> {
+        let port = std::env::var("PORT")
+            .ok()
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(8000);
+        let bind_addrs = match std::env::var("BIND_ADDRS") {
+            Ok(addrs) => addrs
+                .split(',')
+                .map(str::trim)
+                .filter(|addr| !addr.is_empty())
+                .map(|addr| parse_bind_addr(addr, port))
+                .collect::<Result<Vec<_>>>()?,
+            Err(_) => vec![SocketAddr::from(([0, 0, 0, 0], port))],
+        };
+        let unix_socket = std::env::var_os("UNIX_SOCKET").map(PathBuf::from);
+        let tls = match (std::env::var_os("TLS_CERT"), std::env::var_os("TLS_KEY")) {
+            (Some(cert), Some(key)) => Some(TlsFiles {
+                cert: cert.into(),
+                key: key.into(),
+            }),
+            (None, None) => None,
+            _ => bail!("TLS_CERT and TLS_KEY must be set together"),
+        };
+        if bind_addrs.is_empty() && unix_socket.is_none() {
+            bail!("BIND_ADDRS is empty and no UNIX_SOCKET is set");
+        }
+        Ok(Self {
+            bind_addrs,
+            unix_socket,
+            tls,
+        })
+    }
+}
+
+fn parse_bind_addr(addr: &str, port: u16) -> Result<SocketAddr> {
+    if let Ok(addr) = addr.parse() {
+        return Ok(addr);
+    }
+    let ip: IpAddr = addr
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .with_context(|| format!("Invalid bind address {}", addr))?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// TLS acceptor whose certificate can be swapped while the server is running.
+#[derive(Clone)]
+pub struct TlsReloader {
+    files: Arc<TlsFiles>,
+    acceptor: Arc<RwLock<TlsAcceptor>>,
+}
+
+impl TlsReloader {
+    pub fn load(files: TlsFiles) -> Result<Self> {
+        let acceptor = TlsAcceptor::from(Arc::new(load_tls_config(&files)?));
+        Ok(Self {
+            files: Arc::new(files),
+            acceptor: Arc::new(RwLock::new(acceptor)),
+        })
+    }
+
+    /// Reads the certificate and key again. New connections use them, open ones keep
+    /// the old ones. On error the previous certificate stays in use.
+    pub fn reload(&self) -> Result<()> {
+        let acceptor = TlsAcceptor::from(Arc::new(load_tls_config(&self.files)?));
+        *self
+            .acceptor
+            .write()
+            .map_err(|_| anyhow!("TLS acceptor poisoned"))? = acceptor;
+        Ok(())
+    }
+
+    fn acceptor(&self) -> TlsAcceptor {
+        match self.acceptor.read() {
+            Ok(acceptor) => acceptor.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+fn load_tls_config(files: &TlsFiles) -> Result<ServerConfig> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(
+        File::open(&files.cert).with_context(|| format!("Cant open {}", files.cert.display()))?,
+    ))
+    .with_context(|| format!("Cant read certificates from {}", files.cert.display()))?;
+    if certs.is_empty() {
+        bail!("No certificates in {}", files.cert.display());
+    }
+    let key = rustls_pemfile::read_all(&mut BufReader::new(
+        File::open(&files.key).with_context(|| format!("Cant open {}", files.key.display()))?,
+    ))
+    .with_context(|| format!("Cant read key from {}", files.key.display()))?
+    .into_iter()
+    .find_map(|item| match item {
+        rustls_pemfile::Item::PKCS8Key(key)
+        | rustls_pemfile::Item::RSAKey(key)
+        | rustls_pemfile::Item::ECKey(key) => Some(key),
+        _ => None,
+    })
+    .ok_or_else(|| anyhow!("No private key in {}", files.key.display()))?;
+
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            certs.into_iter().map(Certificate).collect(),
+            PrivateKey(key),
+        )
+        .context("Invalid certificate or key")?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// Binds every configured listener and serves `service` on them until the process exits.
+pub async fn serve<S>(service: S, config: ListenConfig) -> Result<()>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    let tls = config.tls.map(TlsReloader::load).transpose()?;
+    let mut listeners: Vec<BoxFuture<'static, ()>> = vec![];
+
+    for addr in config.bind_addrs {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Cant bind {}", addr))?;
+        tracing::info!(%addr, tls = tls.is_some(), "listening");
+        listeners.push(Box::pin(serve_tcp(listener, tls.clone(), service.clone())));
+    }
+
+    if let Some(path) = config.unix_socket {
+        listeners.push(Box::pin(unix::serve(path, service.clone())?));
+    }
+
+    if let Some(tls) = tls {
+        reload_on_hangup(tls);
+    }
+
+    futures::future::join_all(listeners).await;
+    Ok(())
+}
+
+async fn serve_tcp<S>(listener: TcpListener, tls: Option<TlsReloader>, service: S)
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(error) => {
+                // Usually out of file descriptors, give connections time to close.
+                tracing::warn!(%error, "accept failed");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let acceptor = tls.as_ref().map(TlsReloader::acceptor);
+        let service = service.clone();
+        tokio::spawn(async move {
+            match acceptor {
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => serve_connection(stream, service, Some(peer)).await,
+                    Err(error) => tracing::debug!(%peer, %error, "TLS handshake failed"),
+                },
+                None => serve_connection(stream, service, Some(peer)).await,
+            }
+        });
+    }
+}
+
+async fn serve_connection<I, S>(io: I, service: S, peer: Option<SocketAddr>)
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    let service = hyper::service::service_fn(move |mut request: Request<Body>| {
+        if let Some(peer) = peer {
+            request.extensions_mut().insert(PeerAddr(peer));
+        }
+        service.clone().call(request)
+    });
+    if let Err(error) = Http::new()
+        .serve_connection(io, service)
+        .with_upgrades()
+        .await
+    {
+        tracing::debug!(?peer, %error, "connection error");
+    }
+}
+
+#[cfg(unix)]
+fn reload_on_hangup(tls: TlsReloader) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(error) => {
+            tracing::warn!(%error, "cant listen for SIGHUP, TLS reload disabled");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            match tls.reload() {
+                Ok(()) => tracing::info!("reloaded TLS certificate"),
+                Err(error) => tracing::warn!(?error, "TLS reload failed, keeping old certificate"),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn reload_on_hangup(_tls: TlsReloader) {}
+
+#[cfg(unix)]
+mod unix {
+    use std::{convert::Infallible, future::Future, os::unix::fs::FileTypeExt, path::PathBuf};
+
+    use anyhow::{bail, Context, Result};
+    use tokio::net::UnixListener;
+    use warp::hyper::{service::Service, Body, Request, Response};
+
+    pub fn serve<S>(path: PathBuf, service: S) -> Result<impl Future<Output = ()>>
+    where
+        S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+            + Clone
+            + Send
+            + 'static,
+        S::Future: Send + 'static,
+    {
+        // A socket left behind by a previous run would make bind fail.
+        if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+            if !metadata.file_type().is_socket() {
+                bail!("{} exists and is not a socket", path.display());
+            }
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Cant remove stale socket {}", path.display()))?;
+        }
+        let listener =
+            UnixListener::bind(&path).with_context(|| format!("Cant bind {}", path.display()))?;
+        tracing::info!(path = %path.display(), "listening");
+
+        Ok(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(super::serve_connection(stream, service.clone(), None));
+                    }
+                    Err(error) => {
+                        tracing::warn!(%error, "accept failed");
+                        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(not(unix))]
+mod unix {
+    use std::path::PathBuf;
+
+    use anyhow::{bail, Result};
+
+    pub fn serve<S>(_path: PathBuf, _service: S) -> Result<std::future::Pending<()>> {
+        bail!("UNIX_SOCKET is only supported on Unix")
+    }
+}
@@ -3,15 +3,19 @@ use async_graphql::*;
 use serde::Serialize;
 
 use crate::{
-    data::{Player, Rank, Room, RoomState, ServerResponse},
-    games::{Game, GameTrait, PlayerGameData, PlayerMessages, StartMessages},
+    data::{Room, RoomState},
+    games::{Game, GamePlayer, GameTrait, Player, PlayerMessages, Rank, StartMessages, TeamRank},
 };
-use tokio::sync::mpsc::Sender;
 
+use crate::utils::now_millis;
+
+#[allow(clippy::large_enum_variant)]
 #[derive(Serialize, Union, Clone)]
 pub enum GameEvents {
     GameStarted(GameStarted),
     RoomUpdate(RoomUpdate),
+    GamePaused(GamePaused),
+    GameResumed(GameResumed),
 }
 
 #[derive(Serialize, SimpleObject, Clone)]
@@ -24,9 +28,22 @@ pub struct RoomUpdate {
     pub room: Room,
 }
 
+#[derive(Serialize, SimpleObject, Clone)]
+pub struct GamePaused {
+    pub player: Player,
+}
+
+#[derive(Serialize, SimpleObject, Clone)]
+pub struct GameResumed {
+    pub player: Player,
+}
+
 pub enum PlayerEvents {
     StartGame(StartMessages),
     GameMessage(PlayerMessages),
+    Pause,
+    Resume,
+    Undo,
 }
 
 #[derive(SimpleObject, Serialize, Clone)]
@@ -34,6 +51,46 @@ pub enum PlayerEvents {
 pub struct GameData {
     pub players: Vec<GamePlayer>,
     pub game: Game,
+    /// Host of the room, who can pause and resume the game alone.
+    pub host: String,
+    pub paused: bool,
+    /// Players voting to pause, or to resume while paused.
+    pub pause_votes: Vec<String>,
+    /// Players agreeing to undo the last move, cleared whenever a move is made.
+    pub undo_votes: Vec<String>,
+    /// Milliseconds since the epoch when the game started and ended.
+    pub started_at: u64,
+    pub ended_at: Option<u64>,
+    /// When the current wait for a move began, pushed back by the time spent paused.
+    #[graphql(skip)]
+    pub waiting_since: u64,
+    #[graphql(skip)]
+    pub paused_at: Option<u64>,
+    #[graphql(skip)]
+    pub moves: Vec<MoveRecord>,
+    /// Set while a task waits for the game's deadline.
+    #[graphql(skip)]
+    #[serde(skip)]
+    pub deadline_watched: bool,
+}
+
+/// A move accepted while the game was running.
+#[derive(Serialize, Clone)]
+pub struct MoveRecord {
+    pub player_id: String,
+    pub at: u64,
+    pub think_time: u64,
+}
+
+#[derive(SimpleObject, Serialize, Clone)]
+pub struct PlayerStats {
+    pub player: Player,
+    pub moves: u32,
+    /// Think times in milliseconds, measured from the previous move.
+    pub average_think_time: u64,
+    pub longest_think_time: u64,
+    /// Points scored per move, for games that score moves like Boxes.
+    pub points_per_move: Option<f64>,
 }
 
 #[ComplexObject]
@@ -41,6 +98,21 @@ impl GameData {
     pub async fn leaderboard(&self) -> Vec<Rank> {
         self.get_rankings()
     }
+
+    pub async fn team_leaderboard(&self) -> Vec<TeamRank> {
+        self.get_team_rankings()
+    }
+
+    /// Milliseconds played so far, or in total once the game ended.
+    pub async fn duration(&self) -> u64 {
+        self.ended_at
+            .unwrap_or_else(now_millis)
+            .saturating_sub(self.started_at)
+    }
+
+    pub async fn player_stats(&self) -> Vec<PlayerStats> {
+        self.get_player_stats()
+    }
 }
 
 impl GameData {
@@ -48,35 +120,149 @@ impl GameData {
         self.game.get_rankings(&self.players)
     }
 
+    pub fn get_team_rankings(&self) -> Vec<TeamRank> {
+        self.game.get_team_rankings(&self.players)
+    }
+
+    pub fn get_player_stats(&self) -> Vec<PlayerStats> {
+        self.players
+            .iter()
+            .map(|p| {
+                let think_times = self
+                    .moves
+                    .iter()
+                    .filter(|m| m.player_id == p.player.id)
+                    .map(|m| m.think_time)
+                    .collect::<Vec<_>>();
+                let moves = think_times.len() as u32;
+                PlayerStats {
+                    player: p.player.clone(),
+                    moves,
+                    average_think_time: think_times.iter().sum::<u64>() / (moves.max(1) as u64),
+                    longest_think_time: think_times.iter().copied().max().unwrap_or_default(),
+                    points_per_move: self
+                        .game
+                        .points_scored(&p.player.id)
+                        .map(|points| points as f64 / moves.max(1) as f64),
+                }
+            })
+            .collect()
+    }
+
+    /// Logs a move that was accepted while the game was running and restarts the move clock.
+    pub fn record_move(&mut self, player_id: &str, counts: bool) {
+        let now = now_millis();
+        if counts {
+            self.moves.push(MoveRecord {
+                player_id: player_id.into(),
+                at: now,
+                think_time: now.saturating_sub(self.waiting_since),
+            });
+        }
+        self.waiting_since = now;
+    }
+
     pub fn change_turn(&mut self) {
+        if self.paused {
+            return;
+        }
+        self.pass_turn();
+    }
+
+    /// Passes on the turn of a player about to leave the game, even while paused, as they
+    /// cannot come back for it.
+    pub fn pass_turn_of(&mut self, player_id: &str) {
+        if self.game.can_change_turn(player_id) {
+            self.pass_turn();
+        }
+    }
+
+    fn pass_turn(&mut self) {
         if let Some(player_id) = self.game.get_next_turn_player(&self.players) {
             self.game.change_turn(&player_id);
+            self.waiting_since = now_millis();
         }
     }
 
     pub fn is_game_end(&self) -> bool {
-        self.game.is_game_end(&self.players)
+        !self.paused && self.game.is_game_end(&self.players)
     }
-}
 
-#[derive(Serialize, SimpleObject, Clone)]
-#[graphql(complex)]
-pub struct GamePlayer {
-    pub player: Player,
-    pub data: PlayerGameData,
-
-    #[serde(skip_serializing)]
-    #[graphql(skip)]
-    pub send_channel: Option<Sender<ServerResponse>>,
-}
+    /// Records a vote to pause or resume. The game switches once the host votes or more
+    /// than half of the connected players agree, returning `true` if it did.
+    pub fn vote_pause(&mut self, player_id: &str, pause: bool) -> Result<bool, anyhow::Error> {
+        if !self.players.iter().any(|p| p.player.id == player_id) {
+            return Err(anyhow::anyhow!("Player not in game"));
+        }
+        if self.paused == pause {
+            return Err(anyhow::anyhow!(if pause {
+                "Game already paused"
+            } else {
+                "Game not paused"
+            }));
+        }
+        if !self.pause_votes.iter().any(|v| v == player_id) {
+            self.pause_votes.push(player_id.into());
+        }
+        let connected = self
+            .players
+            .iter()
+            .filter(|p| p.connected)
+            .collect::<Vec<_>>();
+        let votes = connected
+            .iter()
+            .filter(|p| self.pause_votes.contains(&p.player.id))
+            .count();
+        if player_id != self.host && votes * 2 <= connected.len() {
+            return Ok(false);
+        }
+        self.paused = pause;
+        self.pause_votes.clear();
+        if pause {
+            self.paused_at = Some(now_millis());
+        } else {
+            if let Some(paused_at) = self.paused_at.take() {
+                let paused_for = now_millis().saturating_sub(paused_at);
+                self.waiting_since += paused_for;
+                self.game.extend_deadline(paused_for);
+            }
+            // Pass on a turn held by someone who disconnected while the game was paused.
+            if self
+                .players
+                .iter()
+                .any(|p| !p.connected && self.game.can_change_turn(&p.player.id))
+            {
+                self.change_turn();
+            }
+        }
+        Ok(true)
+    }
 
-#[ComplexObject]
-impl GamePlayer {
-    pub async fn is_connected<'ctx>(
-        &self,
-        _ctx: &Context<'_>,
-    ) -> Result<bool, async_graphql::Error> {
-        Ok(self.send_channel.is_some())
+    /// Records a vote to undo the last move, which is reverted once every connected player
+    /// agrees. Returns `true` if the move was undone.
+    pub fn vote_undo(&mut self, player_id: &str) -> Result<bool, anyhow::Error> {
+        if !self.players.iter().any(|p| p.player.id == player_id) {
+            return Err(anyhow::anyhow!("Player not in game"));
+        }
+        if !self.undo_votes.iter().any(|v| v == player_id) {
+            self.undo_votes.push(player_id.into());
+        }
+        if self
+            .players
+            .iter()
+            .filter(|p| p.connected)
+            .any(|p| !self.undo_votes.contains(&p.player.id))
+        {
+            return Ok(false);
+        }
+        let undone_by = self.game.undo_last_move(&mut self.players)?;
+        // Timed draws are not recorded, so the last record may belong to an earlier move.
+        if self.moves.last().is_some_and(|m| m.player_id == undone_by) {
+            self.moves.pop();
+        }
+        self.undo_votes.clear();
+        self.waiting_since = now_millis();
+        Ok(true)
     }
 }
 
@@ -91,6 +277,7 @@ impl Room {
         match player_message {
             PlayerEvents::StartGame(start_message) => match &self.state {
                 crate::data::RoomState::Lobby(data) => {
+                    data.can_start(&start_message)?;
                     let pplayers = data
                         .players
                         .iter()
@@ -104,11 +291,24 @@ impl Room {
                             data: Game::create_player_data(&start_message, &pplayers, &p.player.id),
                             player: p.player,
 
-                            send_channel: p.send_channel,
+                            connected: p.connected,
                         })
                         .collect::<Vec<_>>();
                     let game = Game::start_game(start_message, &players, player_id);
-                    self.state = RoomState::Game(GameData { players, game });
+                    self.state = RoomState::Game(GameData {
+                        players,
+                        game,
+                        host: data.host.clone(),
+                        paused: false,
+                        pause_votes: vec![],
+                        undo_votes: vec![],
+                        started_at: now_millis(),
+                        ended_at: None,
+                        waiting_since: now_millis(),
+                        paused_at: None,
+                        moves: vec![],
+                        deadline_watched: false,
+                    });
                 }
                 crate::data::RoomState::Game(_) => {
                     return Err(anyhow::anyhow!("Game Already Started"))
@@ -117,12 +317,49 @@ impl Room {
             PlayerEvents::GameMessage(message) => match &mut self.state {
                 RoomState::Lobby(_) => return Err(anyhow::anyhow!("Game Not Started")),
                 RoomState::Game(game) => {
+                    if game.paused {
+                        return Err(anyhow::anyhow!("Game Paused"));
+                    }
+                    let counts = game.game.is_game_running();
                     game.game
-                        .handle_player_message(player_id, &mut game.players, message)?
+                        .handle_player_message(player_id, &mut game.players, message)?;
+                    game.undo_votes.clear();
+                    game.record_move(player_id, counts);
+                }
+            },
+            PlayerEvents::Pause | PlayerEvents::Resume => match &mut self.state {
+                RoomState::Lobby(_) => return Err(anyhow::anyhow!("Game Not Started")),
+                RoomState::Game(game) => {
+                    game.vote_pause(player_id, matches!(player_message, PlayerEvents::Pause))?;
+                }
+            },
+            PlayerEvents::Undo => match &mut self.state {
+                RoomState::Lobby(_) => return Err(anyhow::anyhow!("Game Not Started")),
+                RoomState::Game(game) => {
+                    if game.paused {
+                        return Err(anyhow::anyhow!("Game Paused"));
+                    }
+                    game.vote_undo(player_id)?;
                 }
             },
         }
         self.state.handle_game_end();
         Ok(())
     }
+
+    /// Applies the game's deadline once it passed. Returns `true` if the room changed.
+    pub fn expire_deadline(&mut self) -> Result<bool, anyhow::Error> {
+        let game = match &mut self.state {
+            RoomState::Game(game) => game,
+            RoomState::Lobby(_) => return Ok(false),
+        };
+        match game.game.deadline() {
+            Some(deadline) if !game.paused && deadline <= now_millis() => {
+                game.game.handle_deadline(&mut game.players)?;
+                self.state.handle_game_end();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
 }
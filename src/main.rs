@@ -1,45 +1,111 @@
 use std::{collections::HashMap, convert::Infallible, sync::Arc};
 
 use async_graphql::{
-    http::{playground_source, GraphQLPlaygroundConfig},
-    Schema,
+    http::{playground_source, GraphQLPlaygroundConfig, WebSocketProtocols},
+    Data, Schema,
 };
-use async_graphql_warp::{graphql_subscription, GraphQLResponse};
+use async_graphql_warp::{graphql_protocol, GraphQLResponse, GraphQLWebSocket};
 use tokio::sync::RwLock;
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
 use warp::http::Response as HttpResponse;
 use warp::Filter;
 
-pub mod data;
-pub mod games;
-pub mod logic;
-pub mod schema;
-pub mod utils;
-
-use schema::QueryRoot;
-
-use crate::{
+use bingo_backend::{
+    admin::AdminConfig,
     data::Storage,
-    schema::{MutationRoot, Subscription},
+    games::bingo::BoardSizeLimits,
+    listen,
+    ratelimit::{client_ip, ClientIp, RateLimitConfig, RateLimiter},
+    schema::{MutationRoot, QueryRoot, Subscription},
+    ws,
 };
 
+/// Installs the global subscriber. `LOG_FORMAT` selects `json` lines, `pretty` or the
+/// default compact output, and `RUST_LOG` filters as usual.
+fn init_tracing() {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .with_span_events(FmtSpan::CLOSE);
+    match std::env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => builder.json().with_current_span(true).init(),
+        Ok("pretty") => builder.pretty().init(),
+        _ => builder.compact().init(),
+    }
+}
+
+/// Reads `BINGO_MIN_BOARD_SIZE` and `BINGO_MAX_BOARD_SIZE`, falling back to the defaults.
+fn bingo_board_sizes() -> BoardSizeLimits {
+    let default = BoardSizeLimits::default();
+    let var = |name: &str, default: u16| {
+        std::env::var(name)
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(default)
+    };
+    BoardSizeLimits {
+        min: var("BINGO_MIN_BOARD_SIZE", default.min).max(1),
+        max: var("BINGO_MAX_BOARD_SIZE", default.max).min(BoardSizeLimits::MAX),
+    }
+}
+
 #[tokio::main]
 async fn main() {
-    pretty_env_logger::init();
+    init_tracing();
     let private_rooms = Arc::new(RwLock::new(HashMap::new()));
+    // Seconds Bingo players get to ready their boards, 0 for no limit.
+    let board_time_limit = std::env::var("BINGO_BOARD_TIME_LIMIT")
+        .ok()
+        .and_then(|secs| secs.parse::<u64>().ok())
+        .unwrap_or(120);
+    let storage = Storage {
+        private_rooms: private_rooms.clone(),
+        board_time_limit: Some(board_time_limit * 1000).filter(|limit| *limit > 0),
+        bingo_board_sizes: bingo_board_sizes(),
+    };
+    let limiter = RateLimiter::new(RateLimitConfig::from_env());
+    let trust_proxy = limiter.config.trust_proxy;
     let schema = Schema::build(QueryRoot, MutationRoot, Subscription)
-        .data(Storage {
-            private_rooms: private_rooms.clone(),
-        })
+        .data(storage.clone())
+        .data(AdminConfig::from_env())
+        .data(limiter.clone())
         .finish();
 
-    let graphql_post = async_graphql_warp::graphql(schema.clone()).and_then(
-        |(schema, request): (
-            Schema<QueryRoot, MutationRoot, Subscription>,
-            async_graphql::Request,
-        )| async move {
-            Ok::<_, Infallible>(GraphQLResponse::from(schema.execute(request).await))
-        },
-    );
+    let graphql_post = async_graphql_warp::graphql(schema.clone())
+        .and(client_ip(trust_proxy))
+        .and_then(
+            |(schema, request): (
+                Schema<QueryRoot, MutationRoot, Subscription>,
+                async_graphql::Request,
+            ),
+             ip: ClientIp| async move {
+                Ok::<_, Infallible>(GraphQLResponse::from(
+                    schema.execute(request.data(ip)).await,
+                ))
+            },
+        );
+
+    let graphql_subscription = warp::ws()
+        .and(graphql_protocol())
+        .and(client_ip(trust_proxy))
+        .map(
+            move |ws: warp::ws::Ws, protocol: WebSocketProtocols, ip: ClientIp| {
+                let schema = schema.clone();
+                let reply = ws.on_upgrade(move |socket| {
+                    let mut data = Data::default();
+                    data.insert(ip);
+                    GraphQLWebSocket::new(socket, schema, protocol)
+                        .with_data(data)
+                        .serve()
+                });
+                warp::reply::with_header(
+                    reply,
+                    "Sec-WebSocket-Protocol",
+                    protocol.sec_websocket_protocol(),
+                )
+            },
+        );
 
     let graphql_playground = warp::path::end().and(warp::get()).map(|| {
         HttpResponse::builder()
@@ -49,7 +115,8 @@ async fn main() {
             ))
     });
 
-    let routes = graphql_subscription(schema)
+    let routes = ws::route(storage, limiter, trust_proxy)
+        .or(graphql_subscription)
         .or(graphql_playground)
         .or(graphql_post)
         .with(
@@ -60,13 +127,12 @@ async fn main() {
                 .build(),
         );
 
-    warp::serve(routes)
-        .run((
-            [0, 0, 0, 0],
-            std::env::var("PORT")
-                .unwrap_or_else(|_| "8000".into())
-                .parse()
-                .unwrap_or(8000),
-        ))
-        .await;
+    let served = match listen::ListenConfig::from_env() {
+        Ok(config) => listen::serve(warp::service(routes), config).await,
+        Err(error) => Err(error),
+    };
+    if let Err(error) = served {
+        tracing::error!(?error, "server failed");
+        std::process::exit(1);
+    }
 }
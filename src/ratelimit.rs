@@ -0,0 +1,360 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_graphql::{Context, ErrorExtensions};
+use warp::{Filter, Rejection};
+
+use crate::listen::PeerAddr;
+
+/// Buckets kept before idle, fully refilled ones are dropped.
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    CreateRoom,
+    Join,
+    Move,
+    Chat,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::CreateRoom => write!(f, "create room"),
+            Action::Join => write!(f, "join"),
+            Action::Move => write!(f, "move"),
+            Action::Chat => write!(f, "chat"),
+        }
+    }
+}
+
+/// A bucket holding up to `capacity` tokens, refilled over `period`.
+#[derive(Clone, Copy, Debug)]
+pub struct BucketConfig {
+    pub capacity: f64,
+    pub period: Duration,
+}
+
+impl BucketConfig {
+    /// Parses `capacity/seconds`, e.g. `5/60` for five calls a minute.
+    fn from_env(name: &str, default: BucketConfig) -> Self {
+        std::env::var(name)
+            .ok()
+            .and_then(|value| {
+                let (capacity, seconds) = value.split_once('/')?;
+                Some(BucketConfig {
+                    capacity: capacity.trim().parse().ok()?,
+                    period: Duration::from_secs_f64(seconds.trim().parse().ok()?),
+                })
+            })
+            .filter(|c| c.capacity >= 1.0 && !c.period.is_zero())
+            .unwrap_or(default)
+    }
+
+    fn refill_rate(&self) -> f64 {
+        self.capacity / self.period.as_secs_f64()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    pub create_room: BucketConfig,
+    pub join: BucketConfig,
+    pub moves: BucketConfig,
+    pub chat: BucketConfig,
+    pub max_rooms: usize,
+    pub max_subscriptions_per_ip: usize,
+    /// Use the last `X-Forwarded-For` address, the one added by the proxy, as the client IP.
+    pub trust_proxy: bool,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            create_room: BucketConfig {
+                capacity: 5.0,
+                period: Duration::from_secs(60),
+            },
+            join: BucketConfig {
+                capacity: 20.0,
+                period: Duration::from_secs(60),
+            },
+            moves: BucketConfig {
+                capacity: 10.0,
+                period: Duration::from_secs(2),
+            },
+            chat: BucketConfig {
+                capacity: 5.0,
+                period: Duration::from_secs(10),
+            },
+            max_rooms: 10_000,
+            max_subscriptions_per_ip: 20,
+            trust_proxy: false,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            create_room: BucketConfig::from_env("RATE_LIMIT_CREATE_ROOM", default.create_room),
+            join: BucketConfig::from_env("RATE_LIMIT_JOIN", default.join),
+            moves: BucketConfig::from_env("RATE_LIMIT_MOVE", default.moves),
+            chat: BucketConfig::from_env("RATE_LIMIT_CHAT", default.chat),
+            max_rooms: std::env::var("MAX_ROOMS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.max_rooms),
+            max_subscriptions_per_ip: std::env::var("MAX_SUBSCRIPTIONS_PER_IP")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.max_subscriptions_per_ip),
+            trust_proxy: std::env::var("TRUST_PROXY")
+                .map(|v| matches!(v.to_ascii_lowercase().as_str(), "1" | "true"))
+                .unwrap_or(default.trust_proxy),
+        }
+    }
+
+    fn bucket(&self, action: Action) -> BucketConfig {
+        match action {
+            Action::CreateRoom => self.create_room,
+            Action::Join => self.join,
+            Action::Move => self.moves,
+            Action::Chat => self.chat,
+        }
+    }
+}
+
+/// Returned when a caller is over a limit.
+#[derive(Debug, Clone)]
+pub struct RateLimited {
+    pub reason: String,
+    /// How long the caller should wait, if waiting is known to help.
+    pub retry_after: Option<Duration>,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.retry_after {
+            Some(retry_after) => write!(
+                f,
+                "{}, retry after {:.1}s",
+                self.reason,
+                retry_after.as_secs_f64()
+            ),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+impl ErrorExtensions for RateLimited {
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.to_string()).extend_with(|_, e| {
+            e.set("code", "RATE_LIMITED");
+            if let Some(retry_after) = self.retry_after {
+                e.set("retryAfter", retry_after.as_secs_f64().ceil() as u64);
+            }
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn refill(&mut self, config: &BucketConfig, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * config.refill_rate()).min(config.capacity);
+        self.updated = now;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum BucketKey {
+    Ip(Action, IpAddr),
+    Player(Action, String),
+}
+
+impl BucketKey {
+    fn action(&self) -> Action {
+        match self {
+            BucketKey::Ip(action, _) | BucketKey::Player(action, _) => *action,
+        }
+    }
+}
+
+/// Address of the client that sent the current request.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+pub struct RateLimiter {
+    pub config: RateLimitConfig,
+    buckets: Mutex<HashMap<BucketKey, TokenBucket>>,
+    subscriptions: Mutex<HashMap<IpAddr, usize>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+            subscriptions: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Takes one token from both the IP and the player bucket of the action.
+    pub fn check(
+        &self,
+        action: Action,
+        ip: Option<IpAddr>,
+        player_id: Option<&str>,
+    ) -> Result<(), RateLimited> {
+        let config = self.config.bucket(action);
+        let keys = ip
+            .map(|ip| BucketKey::Ip(action, ip))
+            .into_iter()
+            .chain(player_id.map(|p| BucketKey::Player(action, p.to_string())))
+            .collect::<Vec<_>>();
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() > MAX_TRACKED_BUCKETS {
+            self.prune(&mut buckets, now);
+        }
+
+        let mut wait = Duration::ZERO;
+        for key in keys.iter() {
+            let bucket = buckets.entry(key.clone()).or_insert(TokenBucket {
+                tokens: config.capacity,
+                updated: now,
+            });
+            bucket.refill(&config, now);
+            if bucket.tokens < 1.0 {
+                wait = wait.max(Duration::from_secs_f64(
+                    (1.0 - bucket.tokens) / config.refill_rate(),
+                ));
+            }
+        }
+        if !wait.is_zero() {
+            return Err(RateLimited {
+                reason: format!("Too many {} requests", action),
+                retry_after: Some(wait),
+            });
+        }
+        for key in keys.iter() {
+            if let Some(bucket) = buckets.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    fn prune(&self, buckets: &mut HashMap<BucketKey, TokenBucket>, now: Instant) {
+        buckets.retain(|key, bucket| {
+            let config = self.config.bucket(key.action());
+            let mut bucket = *bucket;
+            bucket.refill(&config, now);
+            bucket.tokens < config.capacity
+        });
+    }
+
+    pub fn check_room_count(&self, rooms: usize) -> Result<(), RateLimited> {
+        if rooms >= self.config.max_rooms {
+            Err(RateLimited {
+                reason: "Server is full".into(),
+                retry_after: None,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reserves a subscription slot for the IP, released when the guard is dropped.
+    pub fn acquire_subscription(
+        self: &Arc<Self>,
+        ip: Option<IpAddr>,
+    ) -> Result<SubscriptionGuard, RateLimited> {
+        if let Some(ip) = ip {
+            let mut subscriptions = self.subscriptions.lock().unwrap_or_else(|e| e.into_inner());
+            let count = subscriptions.entry(ip).or_insert(0);
+            if *count >= self.config.max_subscriptions_per_ip {
+                return Err(RateLimited {
+                    reason: "Too many subscriptions".into(),
+                    retry_after: None,
+                });
+            }
+            *count += 1;
+        }
+        Ok(SubscriptionGuard {
+            limiter: self.clone(),
+            ip,
+        })
+    }
+}
+
+pub struct SubscriptionGuard {
+    limiter: Arc<RateLimiter>,
+    ip: Option<IpAddr>,
+}
+
+impl Drop for SubscriptionGuard {
+    fn drop(&mut self) {
+        if let Some(ip) = self.ip {
+            let mut subscriptions = self
+                .limiter
+                .subscriptions
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            if let Some(count) = subscriptions.get_mut(&ip) {
+                *count = count.saturating_sub(1);
+                if *count == 0 {
+                    subscriptions.remove(&ip);
+                }
+            }
+        }
+    }
+}
+
+/// Applies the limiter of the schema, if any, to the current GraphQL request.
+pub fn limit(
+    ctx: &Context<'_>,
+    action: Action,
+    player_id: &str,
+) -> Result<(), async_graphql::Error> {
+    if let Some(limiter) = ctx.data_opt::<Arc<RateLimiter>>() {
+        let ip = ctx.data_opt::<ClientIp>().and_then(|ip| ip.0);
+        limiter
+            .check(action, ip, Some(player_id))
+            .map_err(|e| e.extend())?;
+    }
+    Ok(())
+}
+
+/// Extracts the client address, honouring `X-Forwarded-For` when configured to. Only the
+/// last address is used: earlier ones come from the client and cannot be trusted.
+pub fn client_ip(
+    trust_proxy: bool,
+) -> impl Filter<Extract = (ClientIp,), Error = Rejection> + Clone {
+    warp::addr::remote()
+        .and(warp::ext::optional::<PeerAddr>())
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .map(
+            move |remote: Option<SocketAddr>, peer: Option<PeerAddr>, forwarded: Option<String>| {
+                let forwarded = forwarded
+                    .filter(|_| trust_proxy)
+                    .and_then(|f| f.rsplit(',').next().and_then(|ip| ip.trim().parse().ok()));
+                let remote = remote.or_else(|| peer.map(|PeerAddr(addr)| addr));
+                ClientIp(forwarded.or_else(|| remote.map(|r| r.ip())))
+            },
+        )
+}
@@ -1,25 +1,42 @@
-use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::sync::Arc;
 
-use async_graphql::{Context, Object, Subscription};
-use futures::Stream;
+use async_graphql::{Context, ErrorExtensions, Object, Subscription};
+use futures::{Stream, StreamExt};
 use tokio::sync::mpsc::channel;
 use tokio::sync::mpsc::Receiver;
-use tokio::sync::RwLock;
+use tracing::Instrument;
 
+use crate::admin::AdminConfig;
+use crate::admin::AdminMutation;
+use crate::admin::AdminQuery;
 use crate::data::ChatMessage;
+use crate::data::GameMessage;
+use crate::data::GameProposal;
+use crate::data::LobbyData;
+use crate::data::LobbyUpdate;
 use crate::data::PlayerConnected;
 use crate::data::PlayerJoined;
 use crate::data::PlayerLeft;
 use crate::data::PlayerRemoved;
+use crate::data::ResyncRequired;
+use crate::data::RoomEvent;
 use crate::data::RoomState;
 use crate::data::ServerResponse;
-use crate::games::Game;
-use crate::games::GameInputs;
+use crate::data::TeamChanged;
+use crate::data::UndoVote;
+use crate::data::MAX_ROOM_CAPACITY;
+use crate::data::SUBSCRIBER_BUFFER_SIZE;
+use crate::games::bingo::{ClaimPenalty, WinPattern};
+use crate::games::GameKind;
 use crate::games::GameTrait;
+use crate::inputs::GameInputs;
+use crate::logic::{GameEvents, GamePaused, GameResumed, PlayerEvents, RoomUpdate};
+use crate::ratelimit::{limit, Action, ClientIp, RateLimiter, SubscriptionGuard};
 use crate::{
-    data::{Player, Room, Storage},
-    utils::generate_rand_string,
+    data::{Room, Storage},
+    games::Player,
+    utils::{generate_rand_string, now_millis},
 };
 
 pub struct QueryRoot;
@@ -31,28 +48,58 @@ impl QueryRoot {
     }
 
     pub async fn game_event(&self, player_id: String, room_id: String) -> GameInputs {
-        Game::input_handler(room_id, player_id)
+        GameInputs { room_id, player_id }
     }
 
     pub async fn ping(&self) -> String {
         "pong".into()
     }
 
+    pub async fn admin(
+        &self,
+        ctx: &Context<'_>,
+        token: String,
+    ) -> Result<AdminQuery, async_graphql::Error> {
+        ctx.data::<AdminConfig>()?.authorize(&token)?;
+        Ok(AdminQuery)
+    }
 }
 
 pub struct MutationRoot;
 
 #[Object]
 impl MutationRoot {
-    pub async fn create_lobby<'ctx>(
+    pub async fn admin(
+        &self,
+        ctx: &Context<'_>,
+        token: String,
+    ) -> Result<AdminMutation, async_graphql::Error> {
+        ctx.data::<AdminConfig>()?.authorize(&token)?;
+        Ok(AdminMutation)
+    }
+
+    #[tracing::instrument(skip(self, ctx, player_name), fields(room_id))]
+    pub async fn create_lobby(
         &self,
         ctx: &Context<'_>,
         player_id: String,
         player_name: String,
+        capacity: Option<u32>,
     ) -> Result<String, async_graphql::Error> {
+        limit(ctx, Action::CreateRoom, &player_id)?;
+        let capacity = capacity.unwrap_or(MAX_ROOM_CAPACITY);
+        if !(1..=MAX_ROOM_CAPACITY).contains(&capacity) {
+            return Err(format!("Capacity must be between 1 and {}", MAX_ROOM_CAPACITY).into());
+        }
         let data = ctx.data::<Storage>()?;
-        let mut rooms = data.private_rooms.write().await;
+        let mut rooms = data.write_rooms().await;
+        if let Some(limiter) = ctx.data_opt::<Arc<RateLimiter>>() {
+            limiter
+                .check_room_count(rooms.len())
+                .map_err(|e| e.extend())?;
+        }
         let room_id = generate_rand_string(6);
+        tracing::Span::current().record("room_id", room_id.as_str());
         if rooms.contains_key(&room_id) {
             Err("Cant create room".into())
         } else {
@@ -63,48 +110,54 @@ impl MutationRoot {
                     Player {
                         id: player_id,
                         name: player_name,
+                        team: None,
                     },
+                    capacity,
                 ),
             );
             Ok(room_id)
         }
     }
 
-    pub async fn join_lobby<'ctx>(
+    #[tracing::instrument(skip(self, ctx, player_name), fields(game))]
+    pub async fn join_lobby(
         &self,
         ctx: &Context<'_>,
         player_id: String,
         player_name: String,
         room_id: String,
     ) -> Result<String, async_graphql::Error> {
+        limit(ctx, Action::Join, &player_id)?;
         let data = ctx.data::<Storage>()?;
         let player = Player {
             id: player_id,
             name: player_name,
+            team: None,
         };
         let room = {
-            let mut rooms = data.private_rooms.write().await;
+            let mut rooms = data.write_rooms().await;
 
             let room = rooms
                 .get_mut(&room_id)
                 .ok_or_else(|| async_graphql::Error::from("Room does not exist"))?;
+            room.state.record_game_kind();
 
-            room.state.add_player(player.clone())?;
+            room.add_player(player.clone())?;
             room.clone()
         };
 
-        room.clone()
-            .state
-            .broadcast(ServerResponse::PlayerJoined(PlayerJoined {
-                player,
+        room.broadcast(ServerResponse::PlayerJoined(PlayerJoined {
+            player,
 
-                room: room.clone(),
-            }))
-            .await;
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
         Ok(room_id)
     }
 
-    pub async fn disconnect<'ctx>(
+    #[tracing::instrument(skip(self, ctx), fields(game))]
+    pub async fn disconnect(
         &self,
         ctx: &Context<'_>,
         player_id: String,
@@ -113,181 +166,599 @@ impl MutationRoot {
         let data = ctx.data::<Storage>()?;
 
         let (room, player) = {
-            let mut rooms = data.private_rooms.write().await;
+            let mut rooms = data.write_rooms().await;
 
             let room = rooms
                 .get_mut(&room_id)
                 .ok_or_else(|| async_graphql::Error::from("Room does not exist"))?;
+            room.state.record_game_kind();
 
-            let player = room.state.remove_player(&player_id)?;
-            if let RoomState::Game(data) = &mut room.state {
-                if data.game.can_change_turn(&player.id) {
-                    data.change_turn();
-                }
-            }
+            let player = room.remove_player(&player_id)?;
             room.state.handle_game_end();
 
             (room.clone(), player)
         };
 
-        room.clone()
-            .state
-            .broadcast(ServerResponse::PlayerRemoved(PlayerRemoved {
-                player,
+        room.broadcast(ServerResponse::PlayerRemoved(PlayerRemoved {
+            player,
 
-                room: room.clone(),
-            }))
-            .await;
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
         Ok("Disconnected".into())
     }
 
+    #[tracing::instrument(skip(self, ctx), fields(game))]
+    pub async fn set_team(
+        &self,
+        ctx: &Context<'_>,
+        player_id: String,
+        room_id: String,
+        team: Option<u32>,
+    ) -> Result<String, async_graphql::Error> {
+        limit(ctx, Action::Move, &player_id)?;
+        let data = ctx.data::<Storage>()?;
+
+        let (room, player) = {
+            let mut rooms = data.write_rooms().await;
+
+            let room = rooms
+                .get_mut(&room_id)
+                .ok_or_else(|| async_graphql::Error::from("Room does not exist"))?;
+            room.state.record_game_kind();
+
+            let player = room.state.set_player_team(&player_id, team)?;
+            (room.clone(), player)
+        };
+
+        room.broadcast(ServerResponse::TeamChanged(TeamChanged {
+            player,
+
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok("Team changed".into())
+    }
 
-    pub async fn chat<'ctx>(
+    #[tracing::instrument(skip(self, ctx), fields(game))]
+    pub async fn set_ready(
         &self,
         ctx: &Context<'_>,
         player_id: String,
         room_id: String,
-        message:String,
+        ready: bool,
+    ) -> Result<bool, async_graphql::Error> {
+        limit(ctx, Action::Move, &player_id)?;
+        let data = ctx.data::<Storage>()?;
+        update_lobby(data, &room_id, |state| {
+            state.set_player_ready(&player_id, ready)
+        })
+        .await?;
+        Ok(ready)
+    }
+
+    /// Proposes the next game, replacing any earlier proposal and clearing every ready flag.
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip(self, ctx), fields(game))]
+    pub async fn propose_game(
+        &self,
+        ctx: &Context<'_>,
+        player_id: String,
+        room_id: String,
+        game: GameKind,
+        board_size: Option<u16>,
+        board_width: Option<u32>,
+        board_height: Option<u32>,
+        pattern: Option<WinPattern>,
+        #[graphql(desc = "Cells of the custom Bingo pattern, row by row")] mask: Option<
+            Vec<Vec<bool>>,
+        >,
+        lines_to_win: Option<u32>,
+        #[graphql(desc = "Draw Bingo numbers from a pool of this many balls, such as 75 or 90")]
+        balls: Option<u32>,
+        #[graphql(desc = "Play Bingo with the numbers from 1 to this, `boardSize²` by default")]
+        pool_size: Option<u32>,
+        #[graphql(desc = "Labels of the Bingo numbers in order, for word or emoji Bingo")]
+        labels: Option<Vec<String>>,
+        #[graphql(desc = "Milliseconds between Bingo or Tambola draws")] call_interval: Option<u64>,
+        #[graphql(desc = "Make Bingo players claim their win, costing a false claim this penalty")]
+        claim_penalty: Option<ClaimPenalty>,
+        #[graphql(desc = "Cards each Bingo player plays at once, 1 by default")] cards: Option<u16>,
+    ) -> Result<bool, async_graphql::Error> {
+        // The `game` field replaces the argument in the span, so it holds the proposed game.
+        tracing::Span::current().record("game", tracing::field::debug(game));
+        limit(ctx, Action::Move, &player_id)?;
+        let data = ctx.data::<Storage>()?;
+        let limits = data.bingo_board_sizes;
+        let pool = GameProposal::bingo_pool(game, board_size, limits, pool_size.or(balls), labels)?;
+        let proposal = GameProposal {
+            game,
+            board_size,
+            win_rules: GameProposal::bingo_rules(
+                game,
+                board_size,
+                limits,
+                pattern,
+                mask,
+                lines_to_win,
+            )?,
+            caller_mode: GameProposal::proposed_caller_mode(
+                game,
+                pool.as_ref(),
+                balls,
+                call_interval,
+            )?,
+            claim_penalty,
+            cards,
+            pool,
+            board_width,
+            board_height,
+            proposed_by: player_id.clone(),
+            votes: vec![],
+            accepted: false,
+        };
+        update_lobby(data, &room_id, |state| {
+            state.propose_game(&player_id, proposal)
+        })
+        .await?;
+        Ok(true)
+    }
+
+    /// Votes for the lobby proposal. Returns `true` once it is accepted.
+    #[tracing::instrument(skip(self, ctx), fields(game))]
+    pub async fn vote_game(
+        &self,
+        ctx: &Context<'_>,
+        player_id: String,
+        room_id: String,
+    ) -> Result<bool, async_graphql::Error> {
+        limit(ctx, Action::Move, &player_id)?;
+        let data = ctx.data::<Storage>()?;
+        let room = update_lobby(data, &room_id, |state| state.vote_proposal(&player_id)).await?;
+        Ok(matches!(
+            &room.state,
+            RoomState::Lobby(LobbyData { proposal: Some(p), .. }) if p.accepted
+        ))
+    }
+
+    /// Votes to pause the running game. Returns `true` once the game is paused.
+    #[tracing::instrument(skip(self, ctx), fields(game))]
+    pub async fn pause_game(
+        &self,
+        ctx: &Context<'_>,
+        player_id: String,
+        room_id: String,
+    ) -> Result<bool, async_graphql::Error> {
+        limit(ctx, Action::Move, &player_id)?;
+        let data = ctx.data::<Storage>()?;
+        Ok(vote_pause(data, &room_id, &player_id, true).await?)
+    }
+
+    /// Votes to resume a paused game. Returns `true` once the game is running again.
+    #[tracing::instrument(skip(self, ctx), fields(game))]
+    pub async fn resume_game(
+        &self,
+        ctx: &Context<'_>,
+        player_id: String,
+        room_id: String,
+    ) -> Result<bool, async_graphql::Error> {
+        limit(ctx, Action::Move, &player_id)?;
+        let data = ctx.data::<Storage>()?;
+        Ok(vote_pause(data, &room_id, &player_id, false).await?)
+    }
+
+    /// Votes to undo the last move. Returns `true` once every connected player agreed and
+    /// the move was reverted.
+    #[tracing::instrument(skip(self, ctx), fields(game))]
+    pub async fn request_undo(
+        &self,
+        ctx: &Context<'_>,
+        player_id: String,
+        room_id: String,
+    ) -> Result<bool, async_graphql::Error> {
+        limit(ctx, Action::Move, &player_id)?;
+        let data = ctx.data::<Storage>()?;
+        Ok(vote_undo(data, &room_id, &player_id).await?)
+    }
+
+    #[tracing::instrument(skip(self, ctx, message), fields(game))]
+    pub async fn chat(
+        &self,
+        ctx: &Context<'_>,
+        player_id: String,
+        room_id: String,
+        message: String,
     ) -> Result<String, async_graphql::Error> {
+        limit(ctx, Action::Chat, &player_id)?;
         let data = ctx.data::<Storage>()?;
 
         let (room, player) = {
-            let mut rooms = data.private_rooms.write().await;
+            let mut rooms = data.write_rooms().await;
 
             let room = rooms
                 .get_mut(&room_id)
                 .ok_or_else(|| async_graphql::Error::from("Room does not exist"))?;
-                
-            let player = room.state.get_player(&player_id).ok_or("Player not in room")?.clone();
+            room.state.record_game_kind();
+
+            let player = room
+                .state
+                .get_player(&player_id)
+                .ok_or("Player not in room")?
+                .clone();
             (room.clone(), player)
         };
 
-        room
-            .state
-            .broadcast(ServerResponse::ChatMessage(ChatMessage {
-                player,
-                message
-            }))
-            .await;
+        room.broadcast(ServerResponse::ChatMessage(ChatMessage {
+            player,
+            message,
+            seq: 0,
+        }))
+        .await;
         Ok("Sucess".into())
     }
 }
 
+/// Applies a change to the lobby of the room and broadcasts the new lobby state.
+pub async fn update_lobby(
+    data: &Storage,
+    room_id: &str,
+    update: impl FnOnce(&mut RoomState) -> Result<(), anyhow::Error>,
+) -> Result<Room, anyhow::Error> {
+    let room = {
+        let mut rooms = data.write_rooms().await;
+
+        let room = rooms
+            .get_mut(room_id)
+            .ok_or_else(|| anyhow::anyhow!("Room does not exist"))?;
+        room.state.record_game_kind();
+        update(&mut room.state)?;
+        room.clone()
+    };
+
+    room.broadcast(ServerResponse::LobbyUpdate(LobbyUpdate {
+        room: room.clone(),
+        seq: 0,
+    }))
+    .await;
+    Ok(room)
+}
+
+/// How often a deadline watcher re-checks the game while waiting
+const DEADLINE_POLL_MS: u64 = 1000;
+
+/// Waits for the deadlines of the game in the room, broadcasting the room each time one is
+/// applied, until the game has none left. The wait is pushed back while the game is paused.
+/// Only one task watches a game, so this can be called after any change to the game.
+pub fn watch_game_deadline(data: &Storage, room_id: &str, room: &Room) {
+    match room.state.as_game() {
+        Some(game) if game.game.deadline().is_some() && !game.deadline_watched => {}
+        _ => return,
+    }
+    let storage = data.clone();
+    let room_id = room_id.to_string();
+    let span = tracing::info_span!("game_deadline", room_id = %room_id);
+    tokio::spawn(
+        async move {
+            let mut watching = false;
+            loop {
+                let wait = {
+                    let mut rooms = storage.write_rooms().await;
+                    let game = match rooms.get_mut(&room_id).map(|room| &mut room.state) {
+                        Some(RoomState::Game(game)) => game,
+                        _ => return,
+                    };
+                    if !watching {
+                        if game.deadline_watched {
+                            return;
+                        }
+                        game.deadline_watched = true;
+                        watching = true;
+                    }
+                    // Never sleep past a poll interval: a move can bring the deadline forward
+                    match game.game.deadline() {
+                        Some(_) if game.paused => DEADLINE_POLL_MS,
+                        Some(deadline) => {
+                            deadline.saturating_sub(now_millis()).min(DEADLINE_POLL_MS)
+                        }
+                        None => {
+                            game.deadline_watched = false;
+                            return;
+                        }
+                    }
+                };
+                tokio::time::sleep(std::time::Duration::from_millis(wait)).await;
+
+                let room = {
+                    let mut rooms = storage.write_rooms().await;
+                    let room = match rooms.get_mut(&room_id) {
+                        Some(room) => room,
+                        None => return,
+                    };
+                    match room.expire_deadline() {
+                        Ok(true) => room.clone(),
+                        Ok(false) => continue,
+                        Err(error) => {
+                            tracing::warn!(%error, "could not apply game deadline");
+                            return;
+                        }
+                    }
+                };
+                tracing::debug!("game deadline passed");
+                room.broadcast(ServerResponse::GameMessage(GameMessage {
+                    event: GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+                    room: room.clone(),
+                    seq: 0,
+                }))
+                .await;
+            }
+        }
+        .instrument(span),
+    );
+}
+
+/// Records a pause or resume vote and broadcasts the outcome, returning `true` if the
+/// game switched.
+pub async fn vote_pause(
+    data: &Storage,
+    room_id: &str,
+    player_id: &str,
+    pause: bool,
+) -> Result<bool, anyhow::Error> {
+    let (room, switched) = {
+        let mut rooms = data.write_rooms().await;
+
+        let room = rooms
+            .get_mut(room_id)
+            .ok_or_else(|| anyhow::anyhow!("Room does not exist"))?;
+        room.state.record_game_kind();
+        room.handle_player_message(
+            player_id,
+            if pause {
+                PlayerEvents::Pause
+            } else {
+                PlayerEvents::Resume
+            },
+        )
+        .await?;
+        let switched = match &room.state {
+            RoomState::Game(data) => data.paused == pause,
+            RoomState::Lobby(_) => true,
+        };
+        (room.clone(), switched)
+    };
+
+    let player = room
+        .state
+        .get_player(player_id)
+        .ok_or_else(|| anyhow::anyhow!("Player not in room"))?
+        .clone();
+    let event = match (switched, pause) {
+        (true, true) => GameEvents::GamePaused(GamePaused { player }),
+        (true, false) => GameEvents::GameResumed(GameResumed { player }),
+        (false, _) => GameEvents::RoomUpdate(RoomUpdate { room: room.clone() }),
+    };
+    room.broadcast(ServerResponse::GameMessage(GameMessage {
+        event,
+        room: room.clone(),
+        seq: 0,
+    }))
+    .await;
+    Ok(switched)
+}
+
+/// Records an undo vote and broadcasts it, returning `true` if the last move was reverted.
+pub async fn vote_undo(
+    data: &Storage,
+    room_id: &str,
+    player_id: &str,
+) -> Result<bool, anyhow::Error> {
+    let (room, accepted) = {
+        let mut rooms = data.write_rooms().await;
+
+        let room = rooms
+            .get_mut(room_id)
+            .ok_or_else(|| anyhow::anyhow!("Room does not exist"))?;
+        room.state.record_game_kind();
+        room.handle_player_message(player_id, PlayerEvents::Undo)
+            .await?;
+        let accepted = match &room.state {
+            RoomState::Game(data) => data.undo_votes.is_empty(),
+            RoomState::Lobby(_) => true,
+        };
+        (room.clone(), accepted)
+    };
+
+    let player = room
+        .state
+        .get_player(player_id)
+        .ok_or_else(|| anyhow::anyhow!("Player not in room"))?
+        .clone();
+    room.broadcast(ServerResponse::UndoVote(UndoVote {
+        player,
+        accepted,
+        room: room.clone(),
+        seq: 0,
+    }))
+    .await;
+    Ok(accepted)
+}
+
 pub struct Subscription;
 
 #[Subscription]
 impl Subscription {
-    async fn server_messages<'ctx>(
+    #[tracing::instrument(skip(self, ctx), fields(game))]
+    async fn server_messages(
         &self,
         ctx: &Context<'_>,
 
         room_id: String,
         player_id: String,
+        #[graphql(desc = "Replays the events sent after this sequence number before live ones")]
+        last_seen_seq: Option<u64>,
     ) -> Result<impl Stream<Item = ServerResponse>, async_graphql::Error> {
-        let (tx, rx) = channel::<ServerResponse>(2);
+        let stream = subscribe(ctx, room_id, player_id, last_seen_seq).await?;
+        Ok(stream.map(|event| event.event))
+    }
+}
 
-        let data = ctx.data::<Storage>()?;
-        let room = {
-            let mut rooms = data.private_rooms.write().await;
+/// Subscribes the player to their room, counting the subscription against the client's
+/// limit.
+async fn subscribe(
+    ctx: &Context<'_>,
+    room_id: String,
+    player_id: String,
+    last_seen_seq: Option<u64>,
+) -> Result<PlayerDisconnected, async_graphql::Error> {
+    let guard = match ctx.data_opt::<Arc<RateLimiter>>() {
+        Some(limiter) => Some(
+            limiter
+                .acquire_subscription(ctx.data_opt::<ClientIp>().and_then(|ip| ip.0))
+                .map_err(|e| e.extend())?,
+        ),
+        None => None,
+    };
+    let data = ctx.data::<Storage>()?;
+    let mut stream = PlayerDisconnected::subscribe(data, room_id, player_id, last_seen_seq).await?;
+    stream.subscription_guard = guard;
+    Ok(stream)
+}
+
+pub struct PlayerDisconnected {
+    player: Player,
+    replay: VecDeque<RoomEvent>,
+    receiver_stream: Receiver<RoomEvent>,
+    storage: Storage,
+    room_id: String,
+    pub subscription_guard: Option<SubscriptionGuard>,
+}
+
+impl PlayerDisconnected {
+    /// Attaches a fresh channel to the player and returns the stream of messages sent to it.
+    /// With `last_seen_seq`, the events sent after it come first, or a single
+    /// [`ResyncRequired`] if they are no longer kept.
+    ///
+    /// The player is disconnected from the room once the returned stream is dropped.
+    pub async fn subscribe(
+        data: &Storage,
+        room_id: String,
+        player_id: String,
+        last_seen_seq: Option<u64>,
+    ) -> Result<Self, anyhow::Error> {
+        let (tx, rx) = channel::<RoomEvent>(SUBSCRIBER_BUFFER_SIZE);
+
+        let (room, replay) = {
+            let mut rooms = data.write_rooms().await;
             let room = rooms
                 .get_mut(&room_id)
-                .ok_or_else(|| async_graphql::Error::from("Room does not exist"))?;
-            room.state.set_player_channel(player_id.clone(), tx)?;
-            room.clone()
+                .ok_or_else(|| anyhow::anyhow!("Room does not exist"))?;
+            room.state.record_game_kind();
+            room.state.connect_player(&player_id)?;
+            let mut events = room.events();
+            events.subscribe(&player_id, tx);
+            let replay = match last_seen_seq {
+                Some(last_seen) => events.since(last_seen).unwrap_or_else(|| {
+                    tracing::debug!(last_seen, "missed events no longer kept");
+                    vec![RoomEvent::new(
+                        events.seq(),
+                        ServerResponse::ResyncRequired(ResyncRequired {
+                            room: room.clone(),
+                            seq: 0,
+                        }),
+                    )]
+                }),
+                None => vec![],
+            };
+            drop(events);
+            (room.clone(), replay)
         };
         let player = room
             .state
             .get_player(&player_id)
-            .ok_or("Player not found ")?
+            .ok_or_else(|| anyhow::anyhow!("Player not found "))?
             .clone();
-        room.clone()
-            .state
-            .broadcast(ServerResponse::PlayerConnected(PlayerConnected {
-                player: player.clone(),
-
-                room: room.clone(),
-            }))
-            .await;
-        let player_dis = PlayerDisconnected {
+        room.broadcast(ServerResponse::PlayerConnected(PlayerConnected {
+            player: player.clone(),
+
+            room: room.clone(),
+            seq: 0,
+        }))
+        .await;
+        Ok(Self {
             player,
+            replay: replay.into(),
             receiver_stream: rx,
-            rooms: ctx.data::<Storage>()?.private_rooms.clone(),
+            storage: data.clone(),
             room_id,
-        };
-        Ok(player_dis)
+            subscription_guard: None,
+        })
     }
 }
 
-pub struct PlayerDisconnected {
-    player: Player,
-    receiver_stream: Receiver<ServerResponse>,
-    rooms: Arc<RwLock<HashMap<String, Room>>>,
-    room_id: String,
-}
-
 impl Drop for PlayerDisconnected {
     fn drop(&mut self) {
-        let rooms = self.rooms.clone();
+        let storage = self.storage.clone();
         let room_id = self.room_id.clone();
         let player = self.player.clone();
-        tokio::spawn(async move {
-            {
-                log::info!("Taking room to remove player {:#?}", player);
-                let mut rooms = rooms.write().await;
-                log::info!("Removing player {:#?}", player);
-                let mut remove = false;
-                if let Some(room) = rooms.get_mut(&room_id) {
-                    if let Err(er) = room.state.disconnect_player(&player.id) {
-                        log::warn!("Could not remove player {:#?}", er)
-                    } else {
-                        log::info!("Player removed {:#?}", player);
-                    }
-                    if room.state.is_empty() {
-                        remove = true;
-                    } else {
-                        log::info!("Sending broadcast PlayerLeft {:#?}", player);
-
-                        log::info!("Updating Turn");
-
-                        if let RoomState::Game(data) = &mut room.state {
+        let span = tracing::info_span!(
+            "player_disconnected",
+            room_id = %room_id,
+            player_id = %player.id
+        );
+        tokio::spawn(
+            async move {
+                {
+                    let mut rooms = storage.write_rooms().await;
+                    let mut remove = false;
+                    if let Some(room) = rooms.get_mut(&room_id) {
+                        if let Err(er) = room.state.disconnect_player(&player.id) {
+                            tracing::warn!(error = %er, "could not disconnect player")
+                        }
+                        if room.state.is_empty() {
+                            remove = true;
+                        } else if let RoomState::Game(data) = &mut room.state {
                             if data.game.can_change_turn(&player.id) {
                                 data.change_turn();
+                                tracing::debug!("turn passed on after disconnect");
                             }
                         }
 
-                        log::info!("Turn Updated")
+                        room.state.handle_game_end();
+                    }
+                    if remove {
+                        rooms.remove(&room_id);
+                        tracing::info!("deleting empty room");
                     }
-
-                    room.state.handle_game_end();
-                }
-                if remove {
-                    rooms.remove(&room_id);
-                    log::info!("Deleting room {:#?}", room_id);
                 }
-            }
-            {
-                let rooms = rooms.read().await;
-                if let Some(room) = rooms.get(&room_id) {
-                    room.clone()
-                        .state
-                        .broadcast(ServerResponse::PlayerLeft(PlayerLeft {
+                {
+                    let rooms = storage.read_rooms().await;
+                    if let Some(room) = rooms.get(&room_id) {
+                        room.broadcast(ServerResponse::PlayerLeft(PlayerLeft {
                             player: player.clone(),
                             room: room.clone(),
+                            seq: 0,
                         }))
                         .await;
+                    }
                 }
             }
-        });
+            .instrument(span),
+        );
     }
 }
 
 impl Stream for PlayerDisconnected {
-    type Item = ServerResponse;
+    type Item = RoomEvent;
 
     fn poll_next(
         mut self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        self.receiver_stream.poll_recv(cx)
+        match self.replay.pop_front() {
+            Some(event) => std::task::Poll::Ready(Some(event)),
+            None => self.receiver_stream.poll_recv(cx),
+        }
     }
 }
@@ -1,5 +1,20 @@
 use rand::{distributions::Alphanumeric, Rng};
 
+/// Milliseconds since the Unix epoch, used for every timestamp sent to clients.
+#[cfg(not(all(target_arch = "wasm32", feature = "wasm")))]
+pub fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// `SystemTime` is not available in the browser, so ask JavaScript for the time.
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+pub fn now_millis() -> u64 {
+    js_sys::Date::now() as u64
+}
+
 pub fn generate_rand_string(length: usize) -> String {
     let lobbyid: String = {
         rand::thread_rng()
@@ -0,0 +1,193 @@
+//! JavaScript bindings to the game rules, built for `wasm32-unknown-unknown` with the
+//! `wasm` feature. Boards are passed as flat arrays, row by row.
+
+use wasm_bindgen::prelude::*;
+
+use crate::games::{
+    bingo::{Board, GameRunning, NumberPool, SelectedCell, WinPattern, WinRules},
+    bluff::{Bluff, Card},
+    boxes::Boxes,
+};
+
+fn js_error(error: anyhow::Error) -> JsError {
+    JsError::new(&error.to_string())
+}
+
+/// Board of numbers from 1 to `pool_size`, or to `board_size²` without one.
+fn bingo_board(
+    numbers: Vec<u32>,
+    board_size: u16,
+    pool_size: Option<u32>,
+) -> Result<Board, JsError> {
+    let size = board_size.max(1) as usize;
+    let rows = numbers.chunks(size).map(<[u32]>::to_vec).collect();
+    match pool_size {
+        Some(pool_size) => Board::with_pool(rows, board_size, pool_size),
+        None => Board::new(rows, board_size),
+    }
+    .map_err(js_error)
+}
+
+/// `pattern` is one of `lines`, `fourCorners`, `x`, `postageStamp`, `blackout` or `custom`,
+/// with `mask` holding the custom cells row by row as `0` or `1`.
+fn win_rules(
+    pattern: Option<String>,
+    mask: Option<Vec<u8>>,
+    lines_to_win: Option<u32>,
+    board_size: u16,
+) -> Result<WinRules, JsError> {
+    let pattern = match pattern.as_deref() {
+        None | Some("lines") => WinPattern::Lines,
+        Some("fourCorners") => WinPattern::FourCorners,
+        Some("x") => WinPattern::X,
+        Some("postageStamp") => WinPattern::PostageStamp,
+        Some("blackout") => WinPattern::Blackout,
+        Some("custom") => WinPattern::Custom,
+        Some(other) => return Err(JsError::new(&format!("Unknown pattern {}", other))),
+    };
+    let size = board_size.max(1) as usize;
+    let mask = mask.map(|mask| {
+        mask.chunks(size)
+            .map(|row| row.iter().map(|cell| *cell != 0).collect())
+            .collect()
+    });
+    WinRules::new(pattern, mask, lines_to_win, board_size).map_err(js_error)
+}
+
+fn called_numbers(called: Vec<u32>) -> Vec<SelectedCell> {
+    called
+        .into_iter()
+        .map(|cell_value| SelectedCell {
+            cell_value,
+            selected_by: String::new(),
+            selected_at: 0,
+        })
+        .collect()
+}
+
+fn card(index: u8) -> Result<Card, JsError> {
+    if index > 51 {
+        return Err(JsError::new("Invalid card"));
+    }
+    Ok(Card::from(index))
+}
+
+/// Throws if the server would reject the board. `pool_size` is the size of the game's pool
+/// of numbers, if it sets one.
+#[wasm_bindgen(js_name = validateBingoBoard)]
+pub fn validate_bingo_board(
+    numbers: Vec<u32>,
+    board_size: u16,
+    pool_size: Option<u32>,
+) -> Result<(), JsError> {
+    bingo_board(numbers, board_size, pool_size).map(|_| ())
+}
+
+/// Patterns completed on the board once the `called` numbers are marked. Classic lines
+/// are scored unless a `pattern` is given.
+#[wasm_bindgen(js_name = bingoScore)]
+pub fn bingo_score(
+    numbers: Vec<u32>,
+    board_size: u16,
+    called: Vec<u32>,
+    pattern: Option<String>,
+    mask: Option<Vec<u8>>,
+    pool_size: Option<u32>,
+) -> Result<u32, JsError> {
+    let rules = win_rules(pattern, mask, None, board_size)?;
+    let board = bingo_board(numbers, board_size, pool_size)?;
+    Ok(board.get_score(&called_numbers(called), &rules))
+}
+
+/// Returns `true` once the board has enough patterns to finish.
+#[wasm_bindgen(js_name = bingoHasCompleted)]
+pub fn bingo_has_completed(
+    numbers: Vec<u32>,
+    board_size: u16,
+    called: Vec<u32>,
+    pattern: Option<String>,
+    mask: Option<Vec<u8>>,
+    lines_to_win: Option<u32>,
+    pool_size: Option<u32>,
+) -> Result<bool, JsError> {
+    let rules = win_rules(pattern, mask, lines_to_win, board_size)?;
+    let board = bingo_board(numbers, board_size, pool_size)?;
+    Ok(board.has_completed(&called_numbers(called), &rules))
+}
+
+/// Returns `true` if `number` can still be called. Given a `pool_size`, the number also has
+/// to be in the game's pool of numbers from 1 to `pool_size`.
+#[wasm_bindgen(js_name = bingoCanCall)]
+pub fn bingo_can_call(called: Vec<u32>, number: u32, pool_size: Option<u32>) -> bool {
+    let in_pool =
+        pool_size.is_none_or(|size| NumberPool { size, labels: None }.check(number).is_ok());
+    in_pool
+        && GameRunning {
+            turn: String::new(),
+            selected_numbers: called_numbers(called),
+            winners: vec![],
+            disqualified: vec![],
+            skip_turn: vec![],
+        }
+        .check_call(number)
+        .is_ok()
+}
+
+/// Cards are numbered 0 to 51, thirteen per suit in the order spades, hearts, clubs and
+/// diamonds, from ace to king. `claimed` is the claim already made this round, if any.
+#[wasm_bindgen(js_name = bluffCanClaim)]
+pub fn bluff_can_claim(claimed: Option<u8>, claim: u8) -> Result<bool, JsError> {
+    let claimed = claimed.map(card).transpose()?;
+    Ok(Bluff::check_claim(claimed.as_ref(), &card(claim)?).is_ok())
+}
+
+/// Returns `true` if flipping the dealt `cards` catches a bluff on `claimed`.
+#[wasm_bindgen(js_name = bluffIsBluff)]
+pub fn bluff_is_bluff(cards: Vec<u8>, claimed: u8) -> Result<bool, JsError> {
+    let cards = cards.into_iter().map(card).collect::<Result<Vec<_>, _>>()?;
+    Ok(Bluff::is_bluff(&cards, &card(claimed)?))
+}
+
+/// A Boxes board that replays moves with the server's rules. Edge ids match the ones
+/// sent by the server.
+#[wasm_bindgen(js_name = BoxesBoard)]
+pub struct BoxesBoard {
+    boxes: Boxes,
+}
+
+#[wasm_bindgen(js_class = BoxesBoard)]
+impl BoxesBoard {
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: u32, height: u32) -> BoxesBoard {
+        BoxesBoard {
+            boxes: Boxes::new(width, height, String::new()),
+        }
+    }
+
+    /// Returns `true` if the edge exists and is still free.
+    #[wasm_bindgen(js_name = canDraw)]
+    pub fn can_draw(&self, edge_id: u32) -> bool {
+        self.boxes.check_edge(edge_id).is_ok()
+    }
+
+    /// Draws the edge for the player. Returns `true` if it completed a box.
+    pub fn draw(&mut self, player_id: &str, edge_id: u32) -> Result<bool, JsError> {
+        self.boxes.occupy(player_id, edge_id).map_err(js_error)
+    }
+
+    /// Owner of every cell row by row, or `undefined` for open cells.
+    pub fn cells(&self) -> Vec<JsValue> {
+        self.boxes
+            .get_cells()
+            .iter()
+            .map(|cell| match &cell.occupied_by {
+                Some(player_id) => JsValue::from_str(player_id),
+                None => JsValue::UNDEFINED,
+            })
+            .collect()
+    }
+
+    pub fn score(&self, player_id: &str) -> u32 {
+        self.boxes.get_score(player_id)
+    }
+}
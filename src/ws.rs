@@ -0,0 +1,716 @@
+use std::sync::Arc;
+
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::Instrument;
+use warp::{
+    ws::{Message, WebSocket, Ws},
+    Filter, Rejection, Reply,
+};
+
+use crate::{
+    data::{
+        ChatMessage, GameMessage, GameProposal, PlayerJoined, ServerResponse, Storage,
+        SUBSCRIBER_BUFFER_SIZE,
+    },
+    games::{
+        bingo::{BingoPlayerMessages, CallerMode, ClaimPenalty, NumberPool, WinPattern, WinRules},
+        bluff::{BluffPlayerMessages, Card, StartBluff},
+        boxes::{BoxesPlayerMessages, BoxesStart, Move},
+        tambola::{Prize, TambolaPlayerMessages, TambolaStart},
+        GameKind, Player, PlayerMessages, StartMessages,
+    },
+    logic::{GameEvents, GameStarted, PlayerEvents, RoomUpdate},
+    ratelimit::{client_ip, Action, ClientIp, RateLimited, RateLimiter},
+    schema::{update_lobby, vote_pause, vote_undo, watch_game_deadline, PlayerDisconnected},
+};
+
+/// Version of the JSON protocol spoken on the plain websocket route.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Deserialize)]
+pub struct ClientFrame {
+    pub version: u32,
+    pub id: Option<u64>,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Command {
+    Join(JoinCommand),
+    Subscribe(SubscribeCommand),
+    Ready(ReadyCommand),
+    Propose(ProposeCommand),
+    VoteGame,
+    Start(StartCommand),
+    Move(MoveCommand),
+    Pause,
+    Resume,
+    Undo,
+    Chat(ChatCommand),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinCommand {
+    pub room_id: String,
+    pub player_id: String,
+    pub player_name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscribeCommand {
+    pub last_seen_seq: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct ReadyCommand {
+    pub ready: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProposeCommand {
+    pub game: GameKind,
+    pub board_size: Option<u16>,
+    pub pattern: Option<WinPattern>,
+    pub mask: Option<Vec<Vec<bool>>>,
+    pub lines_to_win: Option<u32>,
+    pub balls: Option<u32>,
+    pub pool_size: Option<u32>,
+    pub labels: Option<Vec<String>>,
+    pub call_interval: Option<u64>,
+    pub claim_penalty: Option<ClaimPenalty>,
+    pub cards: Option<u16>,
+    pub board_width: Option<u32>,
+    pub board_height: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "game", rename_all = "camelCase")]
+pub enum StartCommand {
+    Bingo {
+        #[serde(rename = "boardSize")]
+        board_size: u16,
+        pattern: Option<WinPattern>,
+        mask: Option<Vec<Vec<bool>>>,
+        #[serde(rename = "linesToWin")]
+        lines_to_win: Option<u32>,
+        balls: Option<u32>,
+        #[serde(rename = "poolSize")]
+        pool_size: Option<u32>,
+        labels: Option<Vec<String>>,
+        #[serde(rename = "callInterval")]
+        call_interval: Option<u64>,
+        #[serde(rename = "claimPenalty")]
+        claim_penalty: Option<ClaimPenalty>,
+        cards: Option<u16>,
+    },
+    Boxes {
+        #[serde(rename = "boardWidth")]
+        board_width: u32,
+        #[serde(rename = "boardHeight")]
+        board_height: u32,
+    },
+    Bluff {
+        seed: u64,
+    },
+    Tambola {
+        #[serde(rename = "callInterval")]
+        call_interval: Option<u64>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum MoveCommand {
+    BingoReadyBoard {
+        #[serde(default)]
+        board: Vec<Vec<u32>>,
+        /// Boards for every card, when playing more than one.
+        #[serde(default)]
+        boards: Vec<Vec<Vec<u32>>>,
+        /// Fill the empty cells of the boards, or all of them, with shuffled numbers.
+        #[serde(default)]
+        randomize: bool,
+    },
+    BingoNumber {
+        number: u32,
+    },
+    BingoCallNext,
+    BingoClaim,
+    BoxesEdge {
+        #[serde(rename = "edgeId")]
+        edge_id: u32,
+    },
+    BluffDeal {
+        cards: Vec<u8>,
+        claim: u8,
+    },
+    BluffPass,
+    BluffFlip,
+    BluffVoteRoundEnd,
+    TambolaCallNext,
+    TambolaClaim {
+        prize: Prize,
+    },
+}
+
+#[derive(Deserialize)]
+pub struct ChatCommand {
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct ServerFrame {
+    pub version: u32,
+    #[serde(flatten)]
+    pub payload: ServerPayload,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ServerPayload {
+    Ack {
+        id: Option<u64>,
+    },
+    Error {
+        id: Option<u64>,
+        message: String,
+    },
+    RateLimited {
+        id: Option<u64>,
+        message: String,
+        #[serde(rename = "retryAfter")]
+        retry_after: Option<u64>,
+    },
+    Event {
+        seq: u64,
+        event: Box<ServerResponse>,
+    },
+}
+
+/// Encoding of the frames, picked with the `encoding` query parameter when connecting.
+///
+/// With a binary encoding the server sends binary messages. Clients may send commands
+/// either as JSON text or as binary messages in the same encoding.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Json,
+    Msgpack,
+    Cbor,
+}
+
+impl Encoding {
+    pub fn encode(&self, frame: &ServerFrame) -> Result<Message, anyhow::Error> {
+        Ok(match self {
+            Encoding::Json => Message::text(serde_json::to_string(frame)?),
+            Encoding::Msgpack => Message::binary(rmp_serde::to_vec_named(frame)?),
+            Encoding::Cbor => {
+                let mut bytes = vec![];
+                ciborium::ser::into_writer(frame, &mut bytes)?;
+                Message::binary(bytes)
+            }
+        })
+    }
+
+    pub fn decode(&self, message: &Message) -> Result<ClientFrame, anyhow::Error> {
+        if let Ok(text) = message.to_str() {
+            return Ok(serde_json::from_str(text)?);
+        }
+        let bytes = message.as_bytes();
+        Ok(match self {
+            Encoding::Json => serde_json::from_slice(bytes)?,
+            Encoding::Msgpack => rmp_serde::from_slice(bytes)?,
+            Encoding::Cbor => ciborium::de::from_reader(bytes)?,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct ConnectQuery {
+    encoding: Option<Encoding>,
+}
+
+/// Room and player a websocket connection has joined as.
+struct Session {
+    room_id: String,
+    player_id: String,
+    subscription: Option<JoinHandle<()>>,
+}
+
+/// Everything a connection needs to run commands against the shared rooms.
+#[derive(Clone)]
+struct Connection {
+    storage: Storage,
+    limiter: Arc<RateLimiter>,
+    ip: ClientIp,
+}
+
+impl Connection {
+    fn limit(&self, action: Action, player_id: &str) -> Result<(), RateLimited> {
+        self.limiter.check(action, self.ip.0, Some(player_id))
+    }
+}
+
+pub fn route(
+    storage: Storage,
+    limiter: Arc<RateLimiter>,
+    trust_proxy: bool,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path("ws")
+        .and(warp::path::end())
+        .and(warp::ws())
+        .and(client_ip(trust_proxy))
+        .and(warp::query::<ConnectQuery>())
+        .map(move |ws: Ws, ip: ClientIp, query: ConnectQuery| {
+            let connection = Connection {
+                storage: storage.clone(),
+                limiter: limiter.clone(),
+                ip,
+            };
+            let encoding = query.encoding.unwrap_or_default();
+            ws.on_upgrade(move |socket| handle_socket(socket, connection, encoding))
+        })
+}
+
+async fn handle_socket(socket: WebSocket, connection: Connection, encoding: Encoding) {
+    let (mut ws_tx, mut ws_rx) = socket.split();
+    // A slow socket holds up the forwarding task, so the room's own bounded channel fills
+    // and the client gets a `ResyncRequired` instead of an ever growing queue.
+    let (out_tx, mut out_rx) = mpsc::channel::<ServerPayload>(SUBSCRIBER_BUFFER_SIZE);
+
+    let writer = tokio::spawn(async move {
+        while let Some(payload) = out_rx.recv().await {
+            let frame = ServerFrame {
+                version: PROTOCOL_VERSION,
+                payload,
+            };
+            match encoding.encode(&frame) {
+                Ok(message) => {
+                    if ws_tx.send(message).await.is_err() {
+                        break;
+                    }
+                }
+                Err(er) => tracing::warn!(error = %er, "could not serialize frame"),
+            }
+        }
+    });
+
+    let mut session: Option<Session> = None;
+    while let Some(Ok(message)) = ws_rx.next().await {
+        if message.is_close() {
+            break;
+        }
+        if !message.is_text() && !message.is_binary() {
+            continue;
+        }
+        let payload = match encoding.decode(&message) {
+            Ok(frame) if frame.version != PROTOCOL_VERSION => ServerPayload::Error {
+                id: frame.id,
+                message: format!("Unsupported protocol version {}", frame.version),
+            },
+            Ok(frame) => {
+                let span = tracing::info_span!(
+                    "ws_command",
+                    room_id = tracing::field::Empty,
+                    player_id = tracing::field::Empty,
+                    game = tracing::field::Empty
+                );
+                if let Some(session) = &session {
+                    span.record("room_id", session.room_id.as_str());
+                    span.record("player_id", session.player_id.as_str());
+                }
+                match handle_command(&connection, &mut session, frame.command, &out_tx)
+                    .instrument(span)
+                    .await
+                {
+                    Ok(()) => ServerPayload::Ack { id: frame.id },
+                    Err(er) => match er.downcast_ref::<RateLimited>() {
+                        Some(limited) => ServerPayload::RateLimited {
+                            id: frame.id,
+                            message: limited.to_string(),
+                            retry_after: limited.retry_after.map(|r| r.as_secs_f64().ceil() as u64),
+                        },
+                        None => ServerPayload::Error {
+                            id: frame.id,
+                            message: er.to_string(),
+                        },
+                    },
+                }
+            }
+            Err(er) => ServerPayload::Error {
+                id: None,
+                message: er.to_string(),
+            },
+        };
+        if out_tx.send(payload).await.is_err() {
+            break;
+        }
+    }
+
+    if let Some(subscription) = session.and_then(|s| s.subscription) {
+        subscription.abort();
+    }
+    writer.abort();
+}
+
+async fn handle_command(
+    connection: &Connection,
+    session: &mut Option<Session>,
+    command: Command,
+    out_tx: &mpsc::Sender<ServerPayload>,
+) -> Result<(), anyhow::Error> {
+    let storage = &connection.storage;
+    match command {
+        Command::Join(join) => {
+            connection.limit(Action::Join, &join.player_id)?;
+            if let Some(subscription) = session.take().and_then(|s| s.subscription) {
+                subscription.abort();
+            }
+            join_room(storage, &join).await?;
+            *session = Some(Session {
+                room_id: join.room_id,
+                player_id: join.player_id,
+                subscription: None,
+            });
+            Ok(())
+        }
+        Command::Subscribe(subscribe) => {
+            let session = session
+                .as_mut()
+                .ok_or_else(|| anyhow::anyhow!("Join a room first"))?;
+            if let Some(subscription) = session.subscription.take() {
+                subscription.abort();
+            }
+            let guard = connection.limiter.acquire_subscription(connection.ip.0)?;
+            let mut stream = PlayerDisconnected::subscribe(
+                storage,
+                session.room_id.clone(),
+                session.player_id.clone(),
+                subscribe.last_seen_seq,
+            )
+            .await?;
+            stream.subscription_guard = Some(guard);
+            let out_tx = out_tx.clone();
+            session.subscription = Some(tokio::spawn(async move {
+                while let Some(event) = stream.next().await {
+                    if out_tx
+                        .send(ServerPayload::Event {
+                            seq: event.seq,
+                            event: Box::new(event.event),
+                        })
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            }));
+            Ok(())
+        }
+        Command::Ready(ready) => {
+            let session = session
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Join a room first"))?;
+            connection.limit(Action::Move, &session.player_id)?;
+            update_lobby(storage, &session.room_id, |state| {
+                state.set_player_ready(&session.player_id, ready.ready)
+            })
+            .await?;
+            Ok(())
+        }
+        Command::Propose(propose) => {
+            let session = session
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Join a room first"))?;
+            connection.limit(Action::Move, &session.player_id)?;
+            let pool = GameProposal::bingo_pool(
+                propose.game,
+                propose.board_size,
+                storage.bingo_board_sizes,
+                propose.pool_size.or(propose.balls),
+                propose.labels,
+            )?;
+            let proposal = GameProposal {
+                game: propose.game,
+                board_size: propose.board_size,
+                win_rules: GameProposal::bingo_rules(
+                    propose.game,
+                    propose.board_size,
+                    storage.bingo_board_sizes,
+                    propose.pattern,
+                    propose.mask,
+                    propose.lines_to_win,
+                )?,
+                caller_mode: GameProposal::proposed_caller_mode(
+                    propose.game,
+                    pool.as_ref(),
+                    propose.balls,
+                    propose.call_interval,
+                )?,
+                claim_penalty: propose.claim_penalty,
+                cards: propose.cards,
+                pool,
+                board_width: propose.board_width,
+                board_height: propose.board_height,
+                proposed_by: session.player_id.clone(),
+                votes: vec![],
+                accepted: false,
+            };
+            update_lobby(storage, &session.room_id, |state| {
+                state.propose_game(&session.player_id, proposal)
+            })
+            .await?;
+            Ok(())
+        }
+        Command::VoteGame => {
+            let session = session
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Join a room first"))?;
+            connection.limit(Action::Move, &session.player_id)?;
+            update_lobby(storage, &session.room_id, |state| {
+                state.vote_proposal(&session.player_id)
+            })
+            .await?;
+            Ok(())
+        }
+        Command::Start(start) => {
+            let session = session
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Join a room first"))?;
+            connection.limit(Action::Move, &session.player_id)?;
+            let start_message = match start {
+                StartCommand::Bingo {
+                    board_size,
+                    pattern,
+                    mask,
+                    lines_to_win,
+                    balls,
+                    pool_size,
+                    labels,
+                    call_interval,
+                    claim_penalty,
+                    cards,
+                } => {
+                    storage.bingo_board_sizes.check(board_size)?;
+                    let pool = NumberPool::new(pool_size.or(balls), labels, board_size)?;
+                    StartMessages::BingoStart(storage.bingo_start(
+                        board_size,
+                        WinRules::new(pattern.unwrap_or_default(), mask, lines_to_win, board_size)?,
+                        CallerMode::new(balls, call_interval, &pool)?,
+                        claim_penalty,
+                        cards,
+                        pool,
+                    )?)
+                }
+                StartCommand::Boxes {
+                    board_width,
+                    board_height,
+                } => StartMessages::BoxesStart(BoxesStart {
+                    board_width,
+                    board_height,
+                }),
+                StartCommand::Bluff { seed } => StartMessages::BluffStart(StartBluff { seed }),
+                StartCommand::Tambola { call_interval } => {
+                    StartMessages::TambolaStart(TambolaStart::new(call_interval)?)
+                }
+            };
+            handle_player_event(storage, session, |_| {
+                Ok(PlayerEvents::StartGame(start_message))
+            })
+            .await
+        }
+        Command::Move(mov) => {
+            let session = session
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Join a room first"))?;
+            connection.limit(Action::Move, &session.player_id)?;
+            handle_player_event(storage, session, |room| {
+                let message = match mov {
+                    MoveCommand::BingoReadyBoard {
+                        board,
+                        mut boards,
+                        randomize,
+                    } => {
+                        if !board.is_empty() {
+                            boards.insert(0, board);
+                        }
+                        let boards = room
+                            .state
+                            .as_game()
+                            .ok_or_else(|| anyhow::anyhow!("Game not running"))?
+                            .game
+                            .as_bingo()
+                            .ok_or_else(|| anyhow::anyhow!("Not Bingo"))?
+                            .make_boards(boards, randomize)?;
+                        PlayerMessages::BingoMessages(BingoPlayerMessages::ReadyBoard(boards))
+                    }
+                    MoveCommand::BingoNumber { number } => {
+                        PlayerMessages::BingoMessages(BingoPlayerMessages::Move(number))
+                    }
+                    MoveCommand::BingoCallNext => {
+                        PlayerMessages::BingoMessages(BingoPlayerMessages::CallNext)
+                    }
+                    MoveCommand::BingoClaim => {
+                        PlayerMessages::BingoMessages(BingoPlayerMessages::ClaimBingo)
+                    }
+                    MoveCommand::BoxesEdge { edge_id } => {
+                        PlayerMessages::BoxesPlayerMessages(BoxesPlayerMessages::Move(Move {
+                            edge_id,
+                        }))
+                    }
+                    MoveCommand::BluffDeal { cards, claim } => {
+                        if cards.iter().chain([&claim]).any(|c| *c > 51) {
+                            return Err(anyhow::anyhow!("Invalid card"));
+                        }
+                        PlayerMessages::BluffPlayerMessages(BluffPlayerMessages::Deal(
+                            cards.into_iter().map(Card::from).collect(),
+                            Card::from(claim),
+                        ))
+                    }
+                    MoveCommand::BluffPass => {
+                        PlayerMessages::BluffPlayerMessages(BluffPlayerMessages::Pass)
+                    }
+                    MoveCommand::BluffFlip => {
+                        PlayerMessages::BluffPlayerMessages(BluffPlayerMessages::Flip)
+                    }
+                    MoveCommand::BluffVoteRoundEnd => {
+                        PlayerMessages::BluffPlayerMessages(BluffPlayerMessages::RaiseEndRound)
+                    }
+                    MoveCommand::TambolaCallNext => {
+                        PlayerMessages::TambolaPlayerMessages(TambolaPlayerMessages::CallNext)
+                    }
+                    MoveCommand::TambolaClaim { prize } => {
+                        PlayerMessages::TambolaPlayerMessages(TambolaPlayerMessages::Claim(prize))
+                    }
+                };
+                Ok(PlayerEvents::GameMessage(message))
+            })
+            .await
+        }
+        Command::Pause | Command::Resume => {
+            let session = session
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Join a room first"))?;
+            connection.limit(Action::Move, &session.player_id)?;
+            vote_pause(
+                storage,
+                &session.room_id,
+                &session.player_id,
+                matches!(command, Command::Pause),
+            )
+            .await?;
+            Ok(())
+        }
+        Command::Undo => {
+            let session = session
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Join a room first"))?;
+            connection.limit(Action::Move, &session.player_id)?;
+            vote_undo(storage, &session.room_id, &session.player_id).await?;
+            Ok(())
+        }
+        Command::Chat(chat) => {
+            let session = session
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Join a room first"))?;
+            connection.limit(Action::Chat, &session.player_id)?;
+            let (room, player) = {
+                let rooms = storage.read_rooms().await;
+                let room = rooms
+                    .get(&session.room_id)
+                    .ok_or_else(|| anyhow::anyhow!("Room does not exist"))?;
+                let player = room
+                    .state
+                    .get_player(&session.player_id)
+                    .ok_or_else(|| anyhow::anyhow!("Player not in room"))?
+                    .clone();
+                (room.clone(), player)
+            };
+            room.broadcast(ServerResponse::ChatMessage(ChatMessage {
+                player,
+                message: chat.message,
+                seq: 0,
+            }))
+            .await;
+            Ok(())
+        }
+    }
+}
+
+async fn join_room(storage: &Storage, join: &JoinCommand) -> Result<(), anyhow::Error> {
+    let player = Player {
+        id: join.player_id.clone(),
+        name: join.player_name.clone(),
+        team: None,
+    };
+    let room = {
+        let mut rooms = storage.write_rooms().await;
+
+        let room = rooms
+            .get_mut(&join.room_id)
+            .ok_or_else(|| anyhow::anyhow!("Room does not exist"))?;
+
+        room.add_player(player.clone())?;
+        room.clone()
+    };
+
+    room.broadcast(ServerResponse::PlayerJoined(PlayerJoined {
+        player,
+
+        room: room.clone(),
+        seq: 0,
+    }))
+    .await;
+    Ok(())
+}
+
+/// Runs an event through [`crate::data::Room::handle_player_message`] and broadcasts the
+/// result exactly like the GraphQL input handlers do.
+async fn handle_player_event(
+    storage: &Storage,
+    session: &Session,
+    event: impl FnOnce(&crate::data::Room) -> Result<PlayerEvents, anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    let (room, started) = {
+        let mut rooms = storage.write_rooms().await;
+
+        let room = rooms
+            .get_mut(&session.room_id)
+            .ok_or_else(|| anyhow::anyhow!("Room does not exist"))?;
+        room.state.record_game_kind();
+        let event = event(room)?;
+        let started = matches!(event, PlayerEvents::StartGame(_));
+        room.handle_player_message(&session.player_id, event)
+            .await?;
+        (room.clone(), started)
+    };
+
+    let event = if started {
+        GameEvents::GameStarted(GameStarted {
+            game: room
+                .state
+                .as_game()
+                .ok_or_else(|| anyhow::anyhow!("Not game"))?
+                .game
+                .clone(),
+        })
+    } else {
+        GameEvents::RoomUpdate(RoomUpdate { room: room.clone() })
+    };
+    room.broadcast(ServerResponse::GameMessage(GameMessage {
+        event,
+        room: room.clone(),
+        seq: 0,
+    }))
+    .await;
+    watch_game_deadline(storage, &session.room_id, &room);
+    Ok(())
+}
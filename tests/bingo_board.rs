@@ -0,0 +1,142 @@
+//! Property tests for the Bingo board checks in `Board::new`, `Board::with_pool` and
+//! `Board::fill`.
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::collections::HashSet;
+
+use bingo_backend::games::bingo::{Board, Cell};
+use proptest::prelude::*;
+
+/// A valid board: the numbers from 1 to `n²` shuffled into `n` rows.
+fn board() -> impl Strategy<Value = (u16, Vec<Vec<Cell>>)> {
+    (1u16..=12).prop_flat_map(|n| {
+        let numbers = (1..=Cell::from(n).pow(2)).collect::<Vec<_>>();
+        Just(numbers).prop_shuffle().prop_map(move |numbers| {
+            let rows = numbers
+                .chunks(n as usize)
+                .map(<[Cell]>::to_vec)
+                .collect::<Vec<_>>();
+            (n, rows)
+        })
+    })
+}
+
+proptest! {
+    #[test]
+    fn accepts_shuffled_boards((n, rows) in board()) {
+        let board = Board::new(rows.clone(), n).unwrap();
+        prop_assert_eq!(board.numbers, rows);
+    }
+
+    #[test]
+    fn rejects_ragged_rows(
+        (n, mut rows) in board(),
+        row in any::<prop::sample::Index>(),
+        longer: bool,
+    ) {
+        let row = row.index(rows.len());
+        if longer {
+            rows[row].push(Cell::from(n).pow(2) + 1);
+        } else {
+            rows[row].pop();
+        }
+        prop_assert!(Board::new(rows, n).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_row_counts((n, mut rows) in board(), more: bool) {
+        if more {
+            rows.push(rows[0].clone());
+        } else {
+            rows.pop();
+        }
+        prop_assert!(Board::new(rows, n).is_err());
+    }
+
+    #[test]
+    fn rejects_duplicates(
+        (n, mut rows) in board().prop_filter("needs two cells", |(n, _)| *n > 1),
+        from in any::<prop::sample::Index>(),
+        to in any::<prop::sample::Index>(),
+    ) {
+        let cells = (n as usize).pow(2);
+        let from = from.index(cells);
+        let to = (from + 1 + to.index(cells - 1)) % cells;
+        let n = n as usize;
+        rows[to / n][to % n] = rows[from / n][from % n];
+        prop_assert!(Board::new(rows.clone(), n as u16).is_err());
+        prop_assert!(Board::fill(rows, n as u16, (n * n) as Cell).is_err());
+    }
+
+    #[test]
+    fn rejects_numbers_outside_the_pool((n, mut rows) in board(), zero: bool) {
+        rows[0][0] = if zero { 0 } else { Cell::from(n).pow(2) + 1 };
+        prop_assert!(Board::new(rows, n).is_err());
+    }
+
+    #[test]
+    fn rejects_boards_of_another_size((n, rows) in board(), bigger: bool) {
+        let size = if bigger { n + 1 } else { n - 1 };
+        prop_assert!(Board::with_pool(rows.clone(), size, 1000).is_err());
+        // Smaller boards are filled up, larger ones are never cut down.
+        prop_assert_eq!(Board::fill(rows, size, 1000).is_ok(), bigger);
+    }
+
+    #[test]
+    fn accepts_numbers_from_a_larger_pool((n, rows) in board(), extra in 0..100u32) {
+        let pool = Cell::from(n).pow(2) + extra;
+        let rows = rows
+            .iter()
+            .map(|row| row.iter().map(|v| v + extra).collect())
+            .collect();
+        prop_assert!(Board::with_pool(rows, n, pool).is_ok());
+    }
+
+    #[test]
+    fn fills_partial_boards((n, rows) in board(), kept in 0..=144usize, pool_extra in 0..50u32) {
+        let cells = (n as usize).pow(2);
+        let partial = rows
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(i, &v)| if i < kept.min(cells) { v } else { 0 })
+            .collect::<Vec<_>>();
+        let partial = partial.chunks(n as usize).map(<[Cell]>::to_vec).collect::<Vec<_>>();
+        let pool = Cell::from(n).pow(2) + pool_extra;
+        let board = Board::fill(partial.clone(), n, pool).unwrap();
+        let mut seen = HashSet::new();
+        for (row, filled) in partial.iter().zip(&board.numbers) {
+            for (&kept, &value) in row.iter().zip(filled) {
+                prop_assert!(kept == 0 || kept == value);
+                prop_assert!((1..=pool).contains(&value));
+                prop_assert!(seen.insert(value));
+            }
+        }
+        prop_assert_eq!(seen.len(), cells);
+    }
+
+    #[test]
+    fn rejects_pools_too_small_for_the_board(n in 1u16..=u16::MAX, short in 1u32..=100) {
+        let needed = Cell::from(n).pow(2);
+        prop_assert!(Board::fill(vec![], n, needed.saturating_sub(short)).is_err());
+        prop_assert!(Board::new(vec![vec![1]; n as usize - 1], n).is_err());
+    }
+}
+
+#[test]
+fn board_sizes_past_u16_squares_do_not_overflow() {
+    // 256² does not fit in a u16.
+    let n: u16 = 256;
+    let cells = Cell::from(n).pow(2);
+    assert_eq!(cells, 65536);
+    let rows = (1..=cells)
+        .collect::<Vec<_>>()
+        .chunks(n as usize)
+        .map(<[Cell]>::to_vec)
+        .collect::<Vec<_>>();
+    let board = Board::new(rows, n).unwrap();
+    assert_eq!(board.numbers[255][255], 65536);
+    assert!(Board::random(n, cells).is_ok());
+    assert!(Board::random(n, cells - 1).is_err());
+    assert!(Board::new(vec![], u16::MAX).is_err());
+}
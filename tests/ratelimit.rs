@@ -0,0 +1,92 @@
+//! Token bucket behaviour of the rate limiter: bursts up to the capacity, refill over the
+//! period and the `retry_after` reported to limited callers.
+
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
+    thread::sleep,
+    time::Duration,
+};
+
+use bingo_backend::ratelimit::{Action, BucketConfig, RateLimitConfig, RateLimiter};
+
+const IP: Option<IpAddr> = Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+/// A limiter allowing `capacity` moves, refilled over `period`.
+fn limiter(capacity: f64, period: Duration) -> Arc<RateLimiter> {
+    RateLimiter::new(RateLimitConfig {
+        moves: BucketConfig { capacity, period },
+        ..RateLimitConfig::default()
+    })
+}
+
+#[test]
+fn allows_bursts_up_to_the_capacity() {
+    let limiter = limiter(3.0, Duration::from_secs(60));
+    for _ in 0..3 {
+        assert!(limiter.check(Action::Move, IP, Some("a")).is_ok());
+    }
+    let limited = limiter.check(Action::Move, IP, Some("a")).unwrap_err();
+    assert_eq!(limited.reason, "Too many move requests");
+    // Other actions have buckets of their own.
+    assert!(limiter.check(Action::Chat, IP, Some("a")).is_ok());
+}
+
+#[test]
+fn retry_after_is_the_time_to_refill_one_token() {
+    // Two tokens a second: an empty bucket has its next token in half a second.
+    let limiter = limiter(2.0, Duration::from_secs(1));
+    limiter.check(Action::Move, None, Some("a")).unwrap();
+    limiter.check(Action::Move, None, Some("a")).unwrap();
+    let retry_after = limiter
+        .check(Action::Move, None, Some("a"))
+        .unwrap_err()
+        .retry_after
+        .unwrap();
+    assert!(retry_after <= Duration::from_millis(500), "{:?}", retry_after);
+    assert!(retry_after > Duration::from_millis(400), "{:?}", retry_after);
+}
+
+#[test]
+fn retry_after_waits_for_the_emptiest_bucket() {
+    let limiter = limiter(2.0, Duration::from_secs(1));
+    // Player `a` empties the IP bucket, so `b` behind the same IP has to wait for it.
+    limiter.check(Action::Move, IP, Some("a")).unwrap();
+    limiter.check(Action::Move, IP, Some("a")).unwrap();
+    let retry_after = limiter
+        .check(Action::Move, IP, Some("b"))
+        .unwrap_err()
+        .retry_after
+        .unwrap();
+    assert!(retry_after > Duration::from_millis(400), "{:?}", retry_after);
+    // Without the IP, `b` still has a full bucket.
+    assert!(limiter.check(Action::Move, None, Some("b")).is_ok());
+}
+
+#[test]
+fn buckets_refill_over_the_period() {
+    // Ten tokens a second.
+    let limiter = limiter(2.0, Duration::from_millis(200));
+    limiter.check(Action::Move, IP, Some("a")).unwrap();
+    limiter.check(Action::Move, IP, Some("a")).unwrap();
+    let retry_after = limiter
+        .check(Action::Move, IP, Some("a"))
+        .unwrap_err()
+        .retry_after
+        .unwrap();
+    // A rejected call takes no token, so waiting out `retry_after` is enough.
+    sleep(retry_after + Duration::from_millis(20));
+    assert!(limiter.check(Action::Move, IP, Some("a")).is_ok());
+    assert!(limiter.check(Action::Move, IP, Some("a")).is_err());
+}
+
+#[test]
+fn refill_stops_at_the_capacity() {
+    let limiter = limiter(2.0, Duration::from_millis(100));
+    limiter.check(Action::Move, IP, Some("a")).unwrap();
+    // Long enough to refill many times over.
+    sleep(Duration::from_millis(500));
+    assert!(limiter.check(Action::Move, IP, Some("a")).is_ok());
+    assert!(limiter.check(Action::Move, IP, Some("a")).is_ok());
+    assert!(limiter.check(Action::Move, IP, Some("a")).is_err());
+}
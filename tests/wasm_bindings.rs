@@ -0,0 +1,101 @@
+//! Runs the `wasm` bindings on `wasm32-unknown-unknown`, where their errors become
+//! JavaScript exceptions. Run it with
+//! `cargo test --target wasm32-unknown-unknown --no-default-features --features wasm`.
+#![cfg(target_arch = "wasm32")]
+
+use bingo_backend::wasm::{
+    bingo_can_call, bingo_has_completed, bingo_score, bluff_can_claim, bluff_is_bluff,
+    validate_bingo_board, BoxesBoard,
+};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// A 3x3 board holding 1 to 9 in order.
+fn board() -> Vec<u32> {
+    (1..=9).collect()
+}
+
+#[wasm_bindgen_test]
+fn accepts_valid_boards() {
+    assert!(validate_bingo_board(board(), 3, None).is_ok());
+    assert!(validate_bingo_board(vec![10, 2, 3, 4, 5, 6, 7, 8, 9], 3, Some(10)).is_ok());
+}
+
+#[wasm_bindgen_test]
+fn rejects_invalid_boards() {
+    // Repeated number
+    assert!(validate_bingo_board(vec![1, 1, 3, 4, 5, 6, 7, 8, 9], 3, None).is_err());
+    // Outside the pool
+    assert!(validate_bingo_board(vec![10, 2, 3, 4, 5, 6, 7, 8, 9], 3, None).is_err());
+    assert!(validate_bingo_board(vec![0, 2, 3, 4, 5, 6, 7, 8, 9], 3, Some(20)).is_err());
+    // Too few or too many numbers
+    assert!(validate_bingo_board(vec![1, 2, 3, 4, 5, 6, 7, 8], 3, None).is_err());
+    assert!(validate_bingo_board((1..=10).collect(), 3, Some(10)).is_err());
+    // Pool too small for the board
+    assert!(validate_bingo_board(board(), 3, Some(8)).is_err());
+}
+
+#[wasm_bindgen_test]
+fn scores_boards() {
+    assert_eq!(bingo_score(board(), 3, vec![1, 2, 3], None, None, None).unwrap(), 1);
+    let x = Some("x".to_string());
+    assert_eq!(bingo_score(board(), 3, vec![1, 5, 9, 3], x.clone(), None, None).unwrap(), 0);
+    assert_eq!(bingo_score(board(), 3, vec![1, 5, 9, 3, 7], x, None, None).unwrap(), 1);
+    assert!(bingo_has_completed(board(), 3, vec![1, 4, 7], None, None, Some(1), None).unwrap());
+    assert!(!bingo_has_completed(board(), 3, vec![1, 4, 7], None, None, Some(2), None).unwrap());
+}
+
+#[wasm_bindgen_test]
+fn rejects_invalid_rules_and_boards_when_scoring() {
+    let unknown = Some("diagonal".to_string());
+    assert!(bingo_score(board(), 3, vec![], unknown, None, None).is_err());
+    // Custom patterns need a mask of the board's size.
+    let custom = Some("custom".to_string());
+    assert!(bingo_score(board(), 3, vec![], custom.clone(), None, None).is_err());
+    assert!(bingo_score(board(), 3, vec![], custom, Some(vec![1; 4]), None).is_err());
+    assert!(bingo_has_completed(vec![1; 9], 3, vec![1], None, None, None, None).is_err());
+}
+
+#[wasm_bindgen_test]
+fn rejects_calls_outside_the_pool() {
+    assert!(bingo_can_call(vec![1, 2], 3, Some(75)));
+    assert!(!bingo_can_call(vec![1, 2], 2, Some(75)));
+    assert!(!bingo_can_call(vec![], 0, Some(75)));
+    assert!(!bingo_can_call(vec![], 76, Some(75)));
+    assert!(bingo_can_call(vec![], 76, None));
+}
+
+#[wasm_bindgen_test]
+fn checks_bluff_claims() {
+    // Claims after the first one in a round repeat it: the ace of spades, not the ace of
+    // hearts.
+    assert!(bluff_can_claim(None, 0).unwrap());
+    assert!(bluff_can_claim(Some(0), 0).unwrap());
+    assert!(!bluff_can_claim(Some(0), 13).unwrap());
+    assert!(!bluff_is_bluff(vec![0, 13, 26], 39).unwrap());
+    assert!(bluff_is_bluff(vec![0, 1], 39).unwrap());
+}
+
+#[wasm_bindgen_test]
+fn rejects_invalid_cards() {
+    assert!(bluff_can_claim(Some(52), 0).is_err());
+    assert!(bluff_can_claim(None, 52).is_err());
+    assert!(bluff_is_bluff(vec![0, 52], 0).is_err());
+    assert!(bluff_is_bluff(vec![0], 255).is_err());
+}
+
+#[wasm_bindgen_test]
+fn boxes_board_rejects_taken_and_missing_edges() {
+    // A single box has edges 1 to 4.
+    let mut board = BoxesBoard::new(1, 1);
+    assert!(board.can_draw(1));
+    assert!(!board.draw("a", 1).unwrap());
+    assert!(!board.can_draw(1));
+    assert!(board.draw("a", 1).is_err());
+    assert!(!board.can_draw(5));
+    assert!(board.draw("a", 5).is_err());
+    board.draw("b", 2).unwrap();
+    board.draw("a", 3).unwrap();
+    assert!(board.draw("b", 4).unwrap());
+    assert_eq!(board.score("b"), 1);
+    assert_eq!(board.cells().len(), 1);
+}
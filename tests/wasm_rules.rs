@@ -0,0 +1,363 @@
+//! Replays sample Bingo, Boxes and Bluff games through the `wasm` helpers and through the
+//! game engines the server runs, checking that both agree after every move. Run it with
+//! `cargo test --features wasm`.
+
+use bingo_backend::{
+    games::{
+        bingo::{
+            Bingo, BingoPlayerMessages, BingoStart, Board, Cell, GameState, NumberPool, WinPattern,
+            WinRules,
+        },
+        bluff::{Bluff, BluffPlayerMessages, Card, StartBluff},
+        boxes::{Boxes, BoxesPlayerMessages, BoxesStart, Move},
+        GamePlayer, GameTrait, Player, PlayerGameData,
+    },
+    wasm::{
+        bingo_can_call, bingo_has_completed, bingo_score, bluff_can_claim, bluff_is_bluff,
+        validate_bingo_board, BoxesBoard,
+    },
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+const PLAYERS: usize = 3;
+
+fn players() -> Vec<Player> {
+    (0..PLAYERS)
+        .map(|i| Player {
+            id: format!("p{}", i),
+            name: format!("Player {}", i),
+            team: None,
+        })
+        .collect()
+}
+
+fn game_players(data: impl Fn(&[Player], &str) -> PlayerGameData) -> Vec<GamePlayer> {
+    let players = players();
+    players
+        .iter()
+        .map(|player| GamePlayer {
+            player: player.clone(),
+            data: data(&players, &player.id),
+            connected: true,
+        })
+        .collect()
+}
+
+const BOARD_SIZE: u16 = 5;
+const POOL_SIZE: Cell = 40;
+
+/// Plays a turn-based Bingo game with `rules`, passing the same pattern to the helpers as
+/// `pattern` and `mask`.
+fn replay_bingo(rules: WinRules, pattern: Option<&str>, mask: Option<Vec<u8>>, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let start = || BingoStart {
+        board_size: BOARD_SIZE,
+        board_time_limit: None,
+        win_rules: rules.clone(),
+        caller_mode: None,
+        claim_penalty: None,
+        cards: 1,
+        pool: NumberPool::new(Some(POOL_SIZE), None, BOARD_SIZE).unwrap(),
+    };
+    let mut players = game_players(|players, id| {
+        PlayerGameData::BingoPlayerData(Bingo::create_player_data(&start(), players, id))
+    });
+    let mut bingo = Bingo::start_game(start(), &players, "p0");
+    for id in players
+        .iter()
+        .map(|p| p.player.id.clone())
+        .collect::<Vec<_>>()
+    {
+        let board = Board::random(BOARD_SIZE, POOL_SIZE).unwrap();
+        let flat = board.numbers.concat();
+        assert!(validate_bingo_board(flat, BOARD_SIZE, Some(POOL_SIZE)).is_ok());
+        bingo
+            .handle_player_message(
+                &id,
+                &mut players,
+                BingoPlayerMessages::ReadyBoard(vec![board]),
+            )
+            .unwrap();
+    }
+
+    let mut numbers = (1..=POOL_SIZE).collect::<Vec<_>>();
+    numbers.shuffle(&mut rng);
+    let mut called = vec![];
+    for number in numbers {
+        let turn = match &bingo.game_state {
+            GameState::GameRunning(data) => data.turn.clone(),
+            _ => unreachable!("turn mode without claims"),
+        };
+        let repeated = called.first().copied().unwrap_or(number);
+        for candidate in [0, number, repeated, POOL_SIZE + 1] {
+            let accepted = bingo
+                .clone()
+                .handle_player_message(
+                    &turn,
+                    &mut players.clone(),
+                    BingoPlayerMessages::Move(candidate),
+                )
+                .is_ok();
+            assert_eq!(
+                bingo_can_call(called.clone(), candidate, Some(POOL_SIZE)),
+                accepted,
+                "calling {} after {:?}",
+                candidate,
+                called
+            );
+        }
+
+        bingo
+            .handle_player_message(&turn, &mut players, BingoPlayerMessages::Move(number))
+            .unwrap();
+        called.push(number);
+        for player in &players {
+            let board = &player.data.as_bingo_player_data().unwrap().boards[0];
+            let flat = board.numbers.concat();
+            let score = bingo_score(
+                flat.clone(),
+                BOARD_SIZE,
+                called.clone(),
+                pattern.map(String::from),
+                mask.clone(),
+                Some(POOL_SIZE),
+            )
+            .unwrap();
+            assert_eq!(score, board.marks.score, "score after {:?}", called);
+            let completed = bingo_has_completed(
+                flat,
+                BOARD_SIZE,
+                called.clone(),
+                pattern.map(String::from),
+                mask.clone(),
+                Some(rules.lines_to_win),
+                Some(POOL_SIZE),
+            )
+            .unwrap();
+            assert_eq!(completed, board.marks.score >= rules.lines_to_win);
+        }
+        if bingo.is_game_end(&players) {
+            return;
+        }
+    }
+    panic!("every number was called without the game ending");
+}
+
+#[test]
+fn bingo_matches_the_engine() {
+    for seed in 0..10 {
+        replay_bingo(WinRules::classic(BOARD_SIZE), None, None, seed);
+        replay_bingo(
+            WinRules::new(WinPattern::Lines, None, Some(2), BOARD_SIZE).unwrap(),
+            Some("lines"),
+            None,
+            seed,
+        );
+        replay_bingo(
+            WinRules::new(WinPattern::X, None, None, BOARD_SIZE).unwrap(),
+            Some("x"),
+            None,
+            seed,
+        );
+        let mask = (0..BOARD_SIZE * BOARD_SIZE)
+            .map(|i| u8::from(i % 3 == 0))
+            .collect::<Vec<_>>();
+        let rows = mask
+            .chunks(BOARD_SIZE as usize)
+            .map(|row| row.iter().map(|cell| *cell != 0).collect())
+            .collect();
+        replay_bingo(
+            WinRules::new(WinPattern::Custom, Some(rows), None, BOARD_SIZE).unwrap(),
+            Some("custom"),
+            Some(mask),
+            seed,
+        );
+    }
+}
+
+fn replay_boxes(width: u32, height: u32, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let start = || BoxesStart {
+        board_width: width,
+        board_height: height,
+    };
+    let mut players = game_players(|players, id| {
+        PlayerGameData::BoxesPlayerData(Boxes::create_player_data(&start(), players, id))
+    });
+    let mut boxes = Boxes::start_game(start(), &players, "p0");
+    let mut board = BoxesBoard::new(width, height);
+
+    let mut edges = boxes
+        .horizontal_edges
+        .iter()
+        .chain(boxes.vertical_edges.iter())
+        .filter_map(|edge| edge.as_unoccupied().map(|edge| edge.id))
+        .collect::<Vec<_>>();
+    edges.shuffle(&mut rng);
+    let missing = edges.iter().max().unwrap() + 1;
+    let mut drawn = vec![];
+    for edge_id in edges {
+        let turn = boxes.turn.clone();
+        for candidate in [edge_id, missing, drawn.first().copied().unwrap_or(edge_id)] {
+            let accepted = boxes
+                .clone()
+                .handle_player_message(
+                    &turn,
+                    &mut players.clone(),
+                    BoxesPlayerMessages::Move(Move { edge_id: candidate }),
+                )
+                .is_ok();
+            assert_eq!(board.can_draw(candidate), accepted, "drawing {}", candidate);
+        }
+
+        let completed = board.draw(&turn, edge_id).unwrap();
+        boxes
+            .handle_player_message(
+                &turn,
+                &mut players,
+                BoxesPlayerMessages::Move(Move { edge_id }),
+            )
+            .unwrap();
+        drawn.push(edge_id);
+        // A completed box keeps the turn with the player who drew it.
+        assert_eq!(completed, boxes.turn == turn, "drawing {}", edge_id);
+        for player in &players {
+            assert_eq!(
+                board.score(&player.player.id),
+                boxes.get_score(&player.player.id)
+            );
+        }
+    }
+    assert!(boxes.is_game_end(&players));
+    let total = players
+        .iter()
+        .map(|p| board.score(&p.player.id))
+        .sum::<u32>();
+    assert_eq!(total, width * height);
+}
+
+#[test]
+fn boxes_matches_the_engine() {
+    for seed in 0..10 {
+        replay_boxes(4, 3, seed);
+        replay_boxes(1, 5, seed);
+    }
+}
+
+fn card_index(card: &Card) -> u8 {
+    (0..52).find(|&i| Card::from(i) == *card).unwrap()
+}
+
+fn hand(players: &[GamePlayer], player_id: &str) -> Vec<u8> {
+    players
+        .iter()
+        .find(|p| p.player.id == player_id)
+        .and_then(|p| p.data.as_bluff_player_data())
+        .map(|data| data.hand().iter().map(card_index).collect())
+        .unwrap_or_default()
+}
+
+fn replay_bluff(seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut players = game_players(|players, id| {
+        PlayerGameData::BluffPlayerData(Bluff::create_player_data(
+            &StartBluff { seed },
+            players,
+            id,
+        ))
+    });
+    let mut bluff = Bluff::start_game(StartBluff { seed }, &players, "p0");
+
+    // Claim of the round, cards dealt last and their dealer, and cards on the table.
+    let mut claimed: Option<u8> = None;
+    let mut last_deal: Option<(String, Vec<u8>)> = None;
+    let mut pile = 0;
+    for _ in 0..60 {
+        if bluff.is_game_end(&players) {
+            return;
+        }
+        let turn = players
+            .iter()
+            .map(|p| p.player.id.clone())
+            .find(|id| bluff.can_change_turn(id))
+            .unwrap();
+        let cards = hand(&players, &turn);
+
+        if let (Some(claim), Some((dealer, dealt))) = (claimed, &last_deal) {
+            if rng.gen_bool(0.4) || cards.is_empty() {
+                let loser = if bluff_is_bluff(dealt.clone(), claim).unwrap() {
+                    dealer.clone()
+                } else {
+                    turn.clone()
+                };
+                let before = hand(&players, &loser).len();
+                bluff
+                    .handle_player_message(&turn, &mut players, BluffPlayerMessages::Flip)
+                    .unwrap();
+                assert_eq!(
+                    hand(&players, &loser).len(),
+                    before + pile,
+                    "flip by {}",
+                    turn
+                );
+                assert!(bluff.can_change_turn(&loser));
+                claimed = None;
+                last_deal = None;
+                pile = 0;
+                continue;
+            }
+        }
+        if cards.is_empty() {
+            bluff
+                .handle_player_message(&turn, &mut players, BluffPlayerMessages::Pass)
+                .unwrap();
+            continue;
+        }
+
+        let count = rng.gen_range(1..=cards.len().min(3));
+        let dealt = cards
+            .choose_multiple(&mut rng, count)
+            .copied()
+            .collect::<Vec<_>>();
+        // Honest deals claim the first dealt card, others any card.
+        let claim = claimed.unwrap_or_else(|| {
+            if rng.gen_bool(0.5) {
+                dealt[0]
+            } else {
+                rng.gen_range(0..52)
+            }
+        });
+        let deal = |claim: u8| {
+            BluffPlayerMessages::Deal(
+                dealt.iter().map(|&i| Card::from(i)).collect(),
+                Card::from(claim),
+            )
+        };
+        for candidate in [claim, (claim + 1) % 52] {
+            let accepted = bluff
+                .clone()
+                .handle_player_message(&turn, &mut players.clone(), deal(candidate))
+                .is_ok();
+            assert_eq!(
+                bluff_can_claim(claimed, candidate).unwrap(),
+                accepted,
+                "claiming {} over {:?}",
+                candidate,
+                claimed
+            );
+        }
+        bluff
+            .handle_player_message(&turn, &mut players, deal(claim))
+            .unwrap();
+        claimed = Some(claim);
+        pile += dealt.len();
+        last_deal = Some((turn, dealt));
+    }
+}
+
+#[test]
+fn bluff_matches_the_engine() {
+    for seed in 0..20 {
+        replay_bluff(seed);
+    }
+}